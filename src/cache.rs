@@ -1,16 +1,32 @@
+use std::ops::{Deref, DerefMut};
+
 pub struct Cache<T> {
-    pub value: T,
-    pub dirty: bool
+    value: T,
+    dirty: bool
 }
 
 impl<T> Cache<T> {
     pub fn new(value: T) -> Cache<T> {
-        return Cache::<T> {
-            value: value,
+        Cache::<T> {
+            value,
             dirty: true,
         }
     }
 
+    // Reads never mark the cache dirty, only DerefMut, set and mark_dirty do
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn set(&mut self, value: T) {
+        self.value = value;
+        self.dirty = true;
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
     pub fn is_dirty(&self) -> bool {
         self.dirty
     }
@@ -20,19 +36,74 @@ impl<T> Cache<T> {
     }
 }
 
+impl<T> Deref for Cache<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Cache<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.value
+    }
+}
+
 pub struct VecCache<T> {
-    pub values: Vec<T>,
-    pub dirty: bool,
+    values: Vec<T>,
+    dirty: bool,
 }
 
 impl<T> VecCache<T> {
     pub fn new(values: Vec<T>) -> VecCache<T> {
-        return VecCache::<T> {
-            values: values,
+        VecCache::<T> {
+            values,
             dirty: true,
         }
     }
 
+    pub fn push(&mut self, value: T) {
+        self.values.push(value);
+        self.dirty = true;
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        self.dirty = true;
+        self.values.remove(index)
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.dirty = true;
+        self.values.iter_mut()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let value = self.values.get_mut(index);
+        if value.is_some() {
+            self.dirty = true;
+        }
+
+        value
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
     pub fn is_dirty(&self) -> bool {
         self.dirty
     }
@@ -41,3 +112,24 @@ impl<T> VecCache<T> {
         self.dirty = false;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_writes_mark_dirty() {
+        let mut cache = Cache::new(1);
+        cache.clear();
+        assert_eq!(*cache.value(), 1);
+        assert_eq!(*cache, 1);
+        assert!(!cache.is_dirty());
+
+        *cache = 2;
+        assert!(cache.is_dirty());
+        cache.clear();
+        cache.set(3);
+        assert!(cache.is_dirty());
+        assert_eq!(*cache.value(), 3);
+    }
+}
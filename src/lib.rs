@@ -1,23 +1,32 @@
-use log::error;
+use cgmath::{EuclideanSpace, Transform as _};
+use log::{error, info, warn};
 use pollster::FutureExt;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use std::thread;
 use std::time;
 use thiserror::Error;
 use winit::{
     application::ApplicationHandler,
-    event::WindowEvent,
-    event_loop::ActiveEventLoop,
+    event::{MouseButton, MouseScrollDelta, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopProxy},
     keyboard::*,
-    window::{Window, WindowId},
+    monitor::{MonitorHandle, VideoModeHandle},
+    raw_window_handle::{HasDisplayHandle, HasWindowHandle},
+    window::{Fullscreen, Window, WindowId},
 };
 
-mod cache;
-mod pipeline;
-mod scene;
+pub mod cache;
+pub mod pipeline;
+pub mod scene;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
-use crate::pipeline::Pipeline;
-use crate::scene::{InitData, Scene};
+use crate::pipeline::{Pipeline, mesh};
+use crate::scene::{
+    CameraMode, InitData, KeyState, Model, ModelLoadOptions, Scene, SceneCommand, Transform,
+};
 
 #[derive(Error, Debug)]
 enum FormatError {
@@ -25,26 +34,271 @@ enum FormatError {
     NotFound,
 }
 
-struct RendererState<'window> {
-    window: Arc<Window>,
+#[derive(Error, Debug)]
+enum AdapterError {
+    #[error("no adapter was compatible with the surface, including the fallback adapter")]
+    NotFound,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AntiAliasing {
+    #[default]
+    None,
+    Msaa(u32),
+    Fxaa,
+}
+
+// What the app does while its window doesn't have focus
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnfocusedMode {
+    #[default]
+    Unchanged,
+    // Renders continuously at no more than the given frames per second
+    Throttle(u32),
+    // Only renders when the window system asks for a redraw
+    Pause,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdapterSelection {
+    Index(usize),
+    Name(String),
+}
+
+const DYNAMIC_RESOLUTION_SMOOTHING: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DynamicResolutionOptions {
+    pub target_frame_time: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    pub step: f32,
+    pub headroom: f32,
+    pub cooldown: f32,
+}
+
+impl Default for DynamicResolutionOptions {
+    fn default() -> Self {
+        DynamicResolutionOptions {
+            target_frame_time: 1.0 / 60.0,
+            min_scale: 0.5,
+            max_scale: 1.0,
+            step: 0.1,
+            headroom: 0.8,
+            cooldown: 0.5,
+        }
+    }
+}
+
+struct DynamicResolution {
+    options: DynamicResolutionOptions,
+    frame_time: f32,
+    cooldown: f32,
+}
+
+impl DynamicResolution {
+    fn new(options: DynamicResolutionOptions) -> DynamicResolution {
+        DynamicResolution {
+            options,
+            frame_time: options.target_frame_time,
+            cooldown: options.cooldown,
+        }
+    }
+
+    // The scale drops when the smoothed frame time is over the target and only
+    // rises again once it falls below the headroom band, so a scale change
+    // that lands near the target does not immediately flip back.
+    fn next_scale(&mut self, render_scale: f32, delta: f32) -> Option<f32> {
+        self.frame_time += (delta - self.frame_time) * DYNAMIC_RESOLUTION_SMOOTHING;
+        self.cooldown -= delta;
+        if self.cooldown > 0.0 {
+            return None;
+        }
+
+        let options = &self.options;
+        let next = if self.frame_time > options.target_frame_time {
+            render_scale - options.step
+        } else if self.frame_time < options.target_frame_time * options.headroom {
+            render_scale + options.step
+        } else {
+            return None;
+        };
+        let next = next.clamp(options.min_scale, options.max_scale);
+        if next == render_scale {
+            return None;
+        }
+
+        self.cooldown = options.cooldown;
+        Some(next)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RendererOptions {
+    pub anti_aliasing: AntiAliasing,
+    pub shading: pipeline::deferred::Shading,
+    pub reverse_z: bool,
+    pub point_shadow_resolution: u32,
+    pub cascade_count: u32,
+    pub cascade_split_lambda: f32,
+    pub directional_shadow_count: u32,
+    pub render_scale: f32,
+    pub upscale_filter: wgpu::FilterMode,
+    // Anisotropic filtering of material textures, 1 turns it off
+    pub anisotropy: u16,
+    pub dynamic_resolution: Option<DynamicResolutionOptions>,
+    pub letterbox_aspect: Option<f32>,
+    pub point_size: f32,
+    pub depth_bias: wgpu::DepthBiasState,
+    pub layer_states: [pipeline::mesh::LayerState; 3],
+    pub background: Option<pipeline::background::Gradient>,
+    pub gizmo: Option<pipeline::gizmo::GizmoOptions>,
+    pub memory_hints: wgpu::MemoryHints,
+    pub backends: wgpu::Backends,
+    pub adapter: Option<AdapterSelection>,
+    pub pipeline_cache_dir: Option<PathBuf>,
+}
+
+impl Default for RendererOptions {
+    fn default() -> Self {
+        RendererOptions {
+            anti_aliasing: AntiAliasing::default(),
+            shading: pipeline::deferred::Shading::default(),
+            reverse_z: false,
+            point_shadow_resolution: pipeline::shadow::POINT_SHADOW_RESOLUTION,
+            cascade_count: pipeline::shadow::CASCADE_COUNT,
+            cascade_split_lambda: pipeline::shadow::CASCADE_SPLIT_LAMBDA,
+            directional_shadow_count: pipeline::shadow::DIRECTIONAL_SHADOW_COUNT,
+            render_scale: 1.0,
+            upscale_filter: wgpu::FilterMode::Linear,
+            anisotropy: 1,
+            dynamic_resolution: None,
+            letterbox_aspect: None,
+            point_size: pipeline::points::POINT_SIZE,
+            depth_bias: pipeline::mesh::DEPTH_BIAS,
+            layer_states: pipeline::mesh::Layer::ALL.map(pipeline::mesh::Layer::default_state),
+            background: None,
+            gizmo: Some(pipeline::gizmo::GizmoOptions::default()),
+            memory_hints: wgpu::MemoryHints::Performance,
+            backends: wgpu::Backends::PRIMARY,
+            adapter: None,
+            pipeline_cache_dir: Some(std::env::temp_dir().join("wgpu_sandbox")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VideoMode {
+    pub monitor: usize,
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate_millihertz: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FullscreenMode {
+    #[default]
+    Windowed,
+    Borderless,
+    Exclusive(VideoMode),
+}
+
+fn collect_video_modes(
+    monitors: impl Iterator<Item = MonitorHandle>,
+) -> Vec<(VideoMode, VideoModeHandle)> {
+    monitors
+        .enumerate()
+        .flat_map(|(monitor, handle)| {
+            handle.video_modes().map(move |video_mode| {
+                let mode = VideoMode {
+                    monitor,
+                    width: video_mode.size().width,
+                    height: video_mode.size().height,
+                    refresh_rate_millihertz: video_mode.refresh_rate_millihertz(),
+                };
+                (mode, video_mode)
+            })
+        })
+        .collect()
+}
+
+// A window the renderer draws into. Winit windows implement it, and hosts that
+// embed the renderer can implement it for their own windows.
+pub trait RenderTarget: HasWindowHandle + HasDisplayHandle + Send + Sync {
+    // Size of the drawable area in physical pixels
+    fn inner_size(&self) -> (u32, u32);
+
+    // Called when the renderer wants another frame, hosts that render
+    // continuously can ignore it
+    fn request_redraw(&self) {}
+}
+
+impl RenderTarget for Window {
+    fn inner_size(&self) -> (u32, u32) {
+        let size = Window::inner_size(self);
+        (size.width, size.height)
+    }
+
+    fn request_redraw(&self) {
+        Window::request_redraw(self);
+    }
+}
+
+type ModelLoader = Box<dyn FnOnce() -> Result<InitData, anyhow::Error> + Send>;
+
+enum LoadState {
+    Uninitialized,
+    Loading(mpsc::Receiver<Result<InitData, anyhow::Error>>),
+    Ready,
+}
+
+pub struct RendererState<'window> {
+    window: Arc<dyn RenderTarget>,
     surface: wgpu::Surface<'window>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     surface_config: wgpu::SurfaceConfiguration,
+    present_modes: Vec<wgpu::PresentMode>,
     is_surface_configured: bool,
-    mesh_pipeline: pipeline::mesh::Mesh,
+    mesh_pipeline: Option<pipeline::mesh::Mesh>,
+    fxaa_pipeline: Option<pipeline::fxaa::Fxaa>,
+    resample_pipeline: Option<pipeline::resample::Resample>,
+    gizmo_pipeline: Option<pipeline::gizmo::Gizmo>,
+    bounds_pipeline: pipeline::bounds::BoundsOverlay,
+    depth_readback: pipeline::readback::DepthReadback,
+    pass_timer: Option<pipeline::timestamps::PassTimer>,
+    pipeline_cache: Option<wgpu::PipelineCache>,
+    pipeline_cache_path: Option<PathBuf>,
+    anti_aliasing: AntiAliasing,
+    sample_count: u32,
+    shading: pipeline::deferred::Shading,
+    reverse_z: bool,
+    point_shadow_resolution: u32,
+    cascade_count: u32,
+    cascade_split_lambda: f32,
+    directional_shadow_count: u32,
+    render_scale: f32,
+    upscale_filter: wgpu::FilterMode,
+    anisotropy: u16,
+    dynamic_resolution: Option<DynamicResolution>,
+    letterbox_aspect: Option<f32>,
+    point_size: f32,
+    depth_bias: wgpu::DepthBiasState,
+    layer_states: [pipeline::mesh::LayerState; 3],
+    background: Option<pipeline::background::Gradient>,
+    color_texture: Option<(wgpu::Texture, wgpu::TextureView)>,
+    scaled_texture: Option<(wgpu::Texture, wgpu::TextureView)>,
     depth_texture: wgpu::Texture,
     depth_texture_view: wgpu::TextureView,
 }
 
 impl<'window> RendererState<'window> {
-    async fn new(
-        window: Arc<Window>,
-        scene: &Scene,
-        init_data: &InitData,
+    pub async fn new(
+        window: Arc<dyn RenderTarget>,
+        options: &RendererOptions,
     ) -> Result<RendererState<'window>, anyhow::Error> {
         let instance_descriptor = wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
+            backends: options.backends,
             #[cfg(debug_assertions)]
             flags: wgpu::InstanceFlags::debugging(),
             #[cfg(not(debug_assertions))]
@@ -55,17 +309,23 @@ impl<'window> RendererState<'window> {
         let instance = wgpu::Instance::new(&instance_descriptor);
         let surface = instance.create_surface(window.clone())?;
 
-        let request_adapter_options = wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
-            ..Default::default()
+        info!("backends: {:?}", options.backends);
+        let adapter = match Self::select_adapter(&instance, &surface, options) {
+            Some(adapter) => adapter,
+            None => Self::request_adapter(&instance, &surface).await?,
         };
-
-        let adapter = instance.request_adapter(&request_adapter_options).await?;
+        info!(
+            "adapter: {} ({:?})",
+            adapter.get_info().name,
+            adapter.get_info().backend
+        );
         let device_descriptor = wgpu::DeviceDescriptor {
             label: Some("Device"),
-            required_features: wgpu::Features::empty(),
-            memory_hints: wgpu::MemoryHints::Performance,
+            required_features: adapter.features()
+                & (wgpu::Features::POLYGON_MODE_LINE
+                    | wgpu::Features::TIMESTAMP_QUERY
+                    | wgpu::Features::PIPELINE_CACHE),
+            memory_hints: options.memory_hints.clone(),
             ..Default::default()
         };
         let (device, queue) = adapter.request_device(&device_descriptor).await?;
@@ -85,8 +345,8 @@ impl<'window> RendererState<'window> {
 
         let config = wgpu::SurfaceConfiguration {
             present_mode: wgpu::PresentMode::AutoVsync,
-            width: window.inner_size().width,
-            height: window.inner_size().height,
+            width: window.inner_size().0,
+            height: window.inner_size().1,
             alpha_mode: wgpu::CompositeAlphaMode::Auto,
             desired_maximum_frame_latency: 2,
             format: surface_format,
@@ -94,25 +354,451 @@ impl<'window> RendererState<'window> {
             view_formats: vec![],
         };
 
-        let (depth_texture, depth_texture_view) = Self::create_depth_texture(&device, &config);
+        let anti_aliasing = match options.anti_aliasing {
+            AntiAliasing::Msaa(count)
+                if !Self::supports_sample_count(&adapter, surface_format, count) =>
+            {
+                warn!(
+                    "{}x MSAA is not supported by the adapter, disabling it",
+                    count
+                );
+                AntiAliasing::None
+            }
+            anti_aliasing => anti_aliasing,
+        };
+        let sample_count = match anti_aliasing {
+            AntiAliasing::Msaa(count) => count,
+            _ => 1,
+        };
+        let anisotropy = Self::supported_anisotropy(&adapter, options.anisotropy);
+
+        let (pipeline_cache, pipeline_cache_path) =
+            Self::create_pipeline_cache(&adapter, &device, options.pipeline_cache_dir.as_ref())
+                .unzip();
+        let render_scale = options.render_scale.max(0.0);
+        let render_config = Self::scaled_config(&device, &config, render_scale);
+        let (depth_texture, depth_texture_view) =
+            Self::create_depth_texture(&device, &render_config, sample_count);
+        let color_texture = Self::create_color_texture(&device, &render_config, anti_aliasing);
+        let fxaa_pipeline = match (anti_aliasing, &color_texture) {
+            (AntiAliasing::Fxaa, Some((_, color_texture_view))) => Some(pipeline::fxaa::Fxaa::new(
+                &device,
+                &config,
+                color_texture_view,
+                pipeline_cache.as_ref(),
+            )),
+            _ => None,
+        };
+        let scaled_texture =
+            Self::create_scaled_texture(&device, &render_config, render_scale, anti_aliasing);
+        let resample_pipeline = scaled_texture.as_ref().map(|(_, scaled_texture_view)| {
+            pipeline::resample::Resample::new(
+                &device,
+                &config,
+                scaled_texture_view,
+                options.upscale_filter,
+                pipeline_cache.as_ref(),
+            )
+        });
+
+        let gizmo_pipeline = options.gizmo.map(|gizmo| {
+            pipeline::gizmo::Gizmo::new(
+                &device,
+                &config,
+                sample_count,
+                gizmo,
+                pipeline_cache.as_ref(),
+            )
+        });
+        let bounds_pipeline = pipeline::bounds::BoundsOverlay::new(
+            &device,
+            &config,
+            sample_count,
+            pipeline_cache.as_ref(),
+        );
+        let depth_readback = pipeline::readback::DepthReadback::new(&device);
+        let pass_timer = pipeline::timestamps::PassTimer::new(&device, &queue);
 
-        let mesh_pipeline = pipeline::mesh::Mesh::new(init_data, scene, &device, &queue, &config);
         Ok(RendererState {
-            window: window,
-            surface: surface,
-            device: device,
-            queue: queue,
+            window,
+            surface,
+            device,
+            queue,
             surface_config: config,
+            present_modes: capabilities.present_modes,
             is_surface_configured: false,
-            mesh_pipeline: mesh_pipeline.unwrap(),
-            depth_texture: depth_texture,
-            depth_texture_view: depth_texture_view,
+            mesh_pipeline: None,
+            fxaa_pipeline,
+            resample_pipeline,
+            gizmo_pipeline,
+            bounds_pipeline,
+            depth_readback,
+            pass_timer,
+            pipeline_cache,
+            pipeline_cache_path,
+            anti_aliasing,
+            sample_count,
+            shading: options.shading,
+            reverse_z: options.reverse_z,
+            point_shadow_resolution: options.point_shadow_resolution,
+            cascade_count: options.cascade_count,
+            cascade_split_lambda: options.cascade_split_lambda,
+            directional_shadow_count: options.directional_shadow_count,
+            render_scale,
+            upscale_filter: options.upscale_filter,
+            anisotropy,
+            dynamic_resolution: options.dynamic_resolution.map(DynamicResolution::new),
+            letterbox_aspect: options.letterbox_aspect.filter(|aspect| *aspect > 0.0),
+            point_size: options.point_size,
+            depth_bias: options.depth_bias,
+            layer_states: options.layer_states,
+            background: options.background,
+            color_texture,
+            scaled_texture,
+            depth_texture,
+            depth_texture_view,
         })
     }
 
+    // Adapters without anisotropic filtering ignore the clamp, so it is turned
+    // off instead of pretending to apply
+    fn supported_anisotropy(adapter: &wgpu::Adapter, anisotropy: u16) -> u16 {
+        let max = if adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING)
+        {
+            pipeline::mesh::MAX_ANISOTROPY
+        } else {
+            1
+        };
+        let supported = anisotropy.clamp(1, max);
+        if supported != anisotropy {
+            warn!(
+                "{}x anisotropic filtering is not supported, using {}x",
+                anisotropy, supported
+            );
+        }
+        supported
+    }
+
+    fn select_adapter(
+        instance: &wgpu::Instance,
+        surface: &wgpu::Surface<'window>,
+        options: &RendererOptions,
+    ) -> Option<wgpu::Adapter> {
+        let adapters = instance.enumerate_adapters(options.backends);
+        for (index, adapter) in adapters.iter().enumerate() {
+            let adapter_info = adapter.get_info();
+            info!(
+                "adapter {}: {} ({:?}, {:?}, driver {})",
+                index,
+                adapter_info.name,
+                adapter_info.backend,
+                adapter_info.device_type,
+                adapter_info.driver
+            );
+        }
+
+        let selection = options.adapter.as_ref()?;
+        let selected = adapters
+            .into_iter()
+            .enumerate()
+            .find(|(index, adapter)| match selection {
+                AdapterSelection::Index(selected) => index == selected,
+                AdapterSelection::Name(name) => adapter
+                    .get_info()
+                    .name
+                    .to_lowercase()
+                    .contains(&name.to_lowercase()),
+            })
+            .map(|(_, adapter)| adapter);
+        match selected {
+            Some(adapter) if adapter.is_surface_supported(surface) => Some(adapter),
+            Some(adapter) => {
+                warn!(
+                    "{} cannot present to the window, choosing an adapter automatically",
+                    adapter.get_info().name
+                );
+                None
+            }
+            None => {
+                warn!(
+                    "no adapter matches {:?}, choosing one automatically",
+                    selection
+                );
+                None
+            }
+        }
+    }
+
+    // Relaxes the request step by step so software adapters such as llvmpipe
+    // are still found when no hardware adapter matches
+    async fn request_adapter(
+        instance: &wgpu::Instance,
+        surface: &wgpu::Surface<'window>,
+    ) -> Result<wgpu::Adapter, AdapterError> {
+        let attempts = [
+            (wgpu::PowerPreference::HighPerformance, false),
+            (wgpu::PowerPreference::None, false),
+            (wgpu::PowerPreference::None, true),
+        ];
+        for (power_preference, force_fallback_adapter) in attempts {
+            info!(
+                "requesting adapter: power preference {:?}, fallback adapter {}",
+                power_preference, force_fallback_adapter
+            );
+            let request_adapter_options = wgpu::RequestAdapterOptions {
+                power_preference,
+                force_fallback_adapter,
+                compatible_surface: Some(surface),
+            };
+            match instance.request_adapter(&request_adapter_options).await {
+                Ok(adapter) => return Ok(adapter),
+                Err(err) => warn!("adapter request failed: {}", err),
+            }
+        }
+
+        Err(AdapterError::NotFound)
+    }
+
+    pub fn load(&mut self, init_data: &InitData, scene: &mut Scene) {
+        scene.camera.set_reverse_z(self.reverse_z);
+        scene.model_bounds = init_data
+            .models
+            .iter()
+            .map(|model| model.bounds())
+            .collect();
+        scene.model_triangle_counts = init_data.models.iter().map(Model::triangle_count).collect();
+        info!("loaded scene: {:?}", scene.stats());
+        if let Some(mesh_pipeline) = self.mesh_pipeline.as_mut() {
+            mesh_pipeline.rebuild(init_data, scene, &self.device, &self.queue);
+            return;
+        }
+
+        let render_config = self.render_config();
+        let mesh_pipeline = pipeline::mesh::Mesh::new(
+            init_data,
+            scene,
+            &self.device,
+            &self.queue,
+            &render_config,
+            self.sample_count,
+            self.pipeline_cache.as_ref(),
+        );
+        let mut mesh_pipeline = match mesh_pipeline {
+            Ok(mesh_pipeline) => mesh_pipeline,
+            Err(err) => {
+                error!("failed to create the mesh pipeline: {}", err);
+                return;
+            }
+        };
+        mesh_pipeline.set_point_shadow_resolution(self.point_shadow_resolution);
+        mesh_pipeline.set_shadow_cascades(self.cascade_count, self.cascade_split_lambda);
+        mesh_pipeline.set_directional_shadow_count(self.directional_shadow_count);
+        mesh_pipeline.set_point_size(self.point_size);
+        mesh_pipeline.set_anisotropy(&self.device, self.anisotropy);
+        mesh_pipeline.set_depth_bias(&self.device, &render_config, self.depth_bias);
+        for layer in pipeline::mesh::Layer::ALL {
+            mesh_pipeline.set_layer_state(&self.device, layer, self.layer_states[layer as usize]);
+        }
+        mesh_pipeline.set_shading(&self.device, &render_config, self.shading);
+        mesh_pipeline.set_background(&self.device, &self.queue, &render_config, self.background);
+        self.mesh_pipeline = Some(mesh_pipeline);
+    }
+
+    fn add_model(&mut self, model: &Model, scene: &mut Scene) {
+        let Some(mesh_pipeline) = self.mesh_pipeline.as_mut() else {
+            error!("cannot add a model before the scene has finished loading");
+            return;
+        };
+
+        // Empty models have nothing to draw and no bounds to frame
+        if model.vertex_count() == 0 {
+            error!("cannot add a model without vertices");
+            return;
+        }
+
+        let index = mesh_pipeline.model_count();
+        let transform = Transform::from_translation(scene.focus.to_vec());
+        scene.add_object(index, transform, 0.5);
+        scene.model_bounds.truncate(index);
+        scene.model_bounds.push(model.bounds());
+        scene.model_triangle_counts.truncate(index);
+        scene.model_triangle_counts.push(model.triangle_count());
+        info!(
+            "added a model with {} vertices and {} triangles",
+            model.vertex_count(),
+            model.triangle_count()
+        );
+        mesh_pipeline.add_model(model, &self.device, &self.queue);
+    }
+
+    // Pipeline caches are only implemented on some backends, and the file is
+    // keyed on the adapter so a cache is never fed to a different driver
+    fn create_pipeline_cache(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        directory: Option<&PathBuf>,
+    ) -> Option<(wgpu::PipelineCache, PathBuf)> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+
+        let path = directory?.join(wgpu::util::pipeline_cache_key(&adapter.get_info())?);
+        let data = std::fs::read(&path).ok();
+        let pipeline_cache_descriptor = wgpu::PipelineCacheDescriptor {
+            label: Some("Pipeline Cache"),
+            data: data.as_deref(),
+            fallback: true,
+        };
+        // SAFETY: the data was produced by get_data for an adapter with the same
+        // key, and fallback replaces it with an empty cache if it fails validation
+        let pipeline_cache = unsafe { device.create_pipeline_cache(&pipeline_cache_descriptor) };
+        Some((pipeline_cache, path))
+    }
+
+    pub fn save_pipeline_cache(&self) {
+        let (Some(pipeline_cache), Some(path)) = (&self.pipeline_cache, &self.pipeline_cache_path)
+        else {
+            return;
+        };
+        let Some(data) = pipeline_cache.get_data() else {
+            return;
+        };
+
+        // Written next to the cache and renamed over it so an interrupted write
+        // never leaves a truncated file behind
+        let temporary_path = path.with_extension("tmp");
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&temporary_path, data))
+            .and_then(|_| std::fs::rename(&temporary_path, path));
+        if let Err(err) = result {
+            warn!(
+                "failed to save the pipeline cache to {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+
+    fn supports_sample_count(
+        adapter: &wgpu::Adapter,
+        format: wgpu::TextureFormat,
+        count: u32,
+    ) -> bool {
+        [format, wgpu::TextureFormat::Depth32Float]
+            .iter()
+            .all(|format| {
+                adapter
+                    .get_texture_format_features(*format)
+                    .flags
+                    .sample_count_supported(count)
+            })
+    }
+
+    fn create_color_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        anti_aliasing: AntiAliasing,
+    ) -> Option<(wgpu::Texture, wgpu::TextureView)> {
+        let (sample_count, usage) = match anti_aliasing {
+            AntiAliasing::None => return None,
+            AntiAliasing::Msaa(count) => (count, wgpu::TextureUsages::RENDER_ATTACHMENT),
+            AntiAliasing::Fxaa => (
+                1,
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            ),
+        };
+
+        let texture_descriptor = wgpu::TextureDescriptor {
+            label: Some("Color Texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                ..Default::default()
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage,
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&texture_descriptor);
+
+        let view_descriptor = wgpu::TextureViewDescriptor {
+            label: Some("Color Texture View"),
+            ..Default::default()
+        };
+        let view = texture.create_view(&view_descriptor);
+
+        Some((texture, view))
+    }
+
+    // Scales the render target size, clamped to what the device can allocate
+    fn scaled_config(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        render_scale: f32,
+    ) -> wgpu::SurfaceConfiguration {
+        let max_dimension = device.limits().max_texture_dimension_2d;
+        let scale =
+            |size: u32| ((size as f32 * render_scale).round() as u32).clamp(1, max_dimension);
+        wgpu::SurfaceConfiguration {
+            width: scale(config.width),
+            height: scale(config.height),
+            ..config.clone()
+        }
+    }
+
+    fn render_config(&self) -> wgpu::SurfaceConfiguration {
+        Self::scaled_config(&self.device, &self.surface_config, self.render_scale)
+    }
+
+    // FXAA already samples its input at the surface size, so only the other
+    // modes need a separate target to resample from.
+    fn create_scaled_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        render_scale: f32,
+        anti_aliasing: AntiAliasing,
+    ) -> Option<(wgpu::Texture, wgpu::TextureView)> {
+        if render_scale == 1.0 || anti_aliasing == AntiAliasing::Fxaa {
+            return None;
+        }
+
+        let texture_descriptor = wgpu::TextureDescriptor {
+            label: Some("Scaled Texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                ..Default::default()
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&texture_descriptor);
+
+        let view_descriptor = wgpu::TextureViewDescriptor {
+            label: Some("Scaled Texture View"),
+            ..Default::default()
+        };
+        let view = texture.create_view(&view_descriptor);
+
+        Some((texture, view))
+    }
+
     fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
     ) -> (wgpu::Texture, wgpu::TextureView) {
         let texture_descriptor = wgpu::TextureDescriptor {
             label: Some("Depth Texture"),
@@ -122,10 +808,10 @@ impl<'window> RendererState<'window> {
                 ..Default::default()
             },
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         };
         let texture = device.create_texture(&texture_descriptor);
@@ -143,43 +829,200 @@ impl<'window> RendererState<'window> {
         };
         let view = texture.create_view(&view_descriptor);
 
-        return (texture, view);
+        (texture, view)
     }
 
-    fn resize(&mut self, width: u32, height: u32, scene: Option<&mut Scene>) {
+    pub fn resize(&mut self, width: u32, height: u32, scene: Option<&mut Scene>) {
         if width > 0 && height > 0 {
             self.is_surface_configured = true;
             self.surface_config.width = width;
             self.surface_config.height = height;
             self.surface.configure(&self.device, &self.surface_config);
-            let (depth_texture, depth_texture_view) =
-                RendererState::create_depth_texture(&self.device, &self.surface_config);
+            let render_config = self.render_config();
+            let (depth_texture, depth_texture_view) = RendererState::create_depth_texture(
+                &self.device,
+                &render_config,
+                self.sample_count,
+            );
             self.depth_texture = depth_texture;
             self.depth_texture_view = depth_texture_view;
+            self.color_texture = RendererState::create_color_texture(
+                &self.device,
+                &render_config,
+                self.anti_aliasing,
+            );
+            self.scaled_texture = RendererState::create_scaled_texture(
+                &self.device,
+                &render_config,
+                self.render_scale,
+                self.anti_aliasing,
+            );
+            if let (Some(fxaa_pipeline), Some((_, color_texture_view))) =
+                (self.fxaa_pipeline.as_mut(), &self.color_texture)
+            {
+                fxaa_pipeline.set_input(&self.device, color_texture_view);
+            }
+            match (self.resample_pipeline.as_mut(), &self.scaled_texture) {
+                (Some(resample_pipeline), Some((_, scaled_texture_view))) => {
+                    resample_pipeline.set_input(&self.device, scaled_texture_view);
+                }
+                (None, Some((_, scaled_texture_view))) => {
+                    self.resample_pipeline = Some(pipeline::resample::Resample::new(
+                        &self.device,
+                        &self.surface_config,
+                        scaled_texture_view,
+                        self.upscale_filter,
+                        self.pipeline_cache.as_ref(),
+                    ));
+                }
+                _ => {}
+            }
+            if let Some(mesh_pipeline) = self.mesh_pipeline.as_mut() {
+                mesh_pipeline.resize(&self.device, render_config.width, render_config.height);
+            }
+            self.window.request_redraw();
 
-            if scene.is_some() {
-                let camera = &mut scene.unwrap().camera;
-                let new_aspect = (self.window.inner_size().width as f32)
-                    / (self.window.inner_size().height as f32);
-                camera.value.update(
-                    camera.value.fov,
-                    new_aspect,
-                    camera.value.near,
-                    camera.value.far,
-                    camera.value.speed,
-                    camera.value.rot_rate,
+            if let Some(scene) = scene {
+                let camera = &mut scene.camera;
+                let new_aspect = width as f32 / height as f32;
+                let (fov, near, far, speed, rot_rate) = (
+                    camera.fov,
+                    camera.near,
+                    camera.far,
+                    camera.speed,
+                    camera.rot_rate,
                 );
+                camera.update(fov, new_aspect, near, far, speed, rot_rate);
             }
         }
     }
 
-    fn render(&self, scene: &mut Scene) -> Result<(), wgpu::SurfaceError> {
+    fn update_dynamic_resolution(&mut self, delta: f32, scene: Option<&mut Scene>) {
+        let Some(dynamic_resolution) = self.dynamic_resolution.as_mut() else {
+            return;
+        };
+        let Some(render_scale) = dynamic_resolution.next_scale(self.render_scale, delta) else {
+            return;
+        };
+
+        info!("render scale: {:.2}", render_scale);
+        self.render_scale = render_scale;
+        self.resize(self.surface_config.width, self.surface_config.height, scene);
+    }
+
+    fn cycle_present_mode(&mut self) {
+        if self.present_modes.is_empty() {
+            return;
+        }
+
+        let next = self
+            .present_modes
+            .iter()
+            .position(|mode| *mode == self.surface_config.present_mode)
+            .map_or(0, |index| (index + 1) % self.present_modes.len());
+        self.surface_config.present_mode = self.present_modes[next];
+        if self.is_surface_configured {
+            self.surface.configure(&self.device, &self.surface_config);
+        }
+        info!("present mode: {:?}", self.surface_config.present_mode);
         self.window.request_redraw();
+    }
+
+    // The largest rectangle of the fixed aspect centered in the target
+    fn letterbox((width, height): (u32, u32), aspect: Option<f32>) -> (u32, u32, u32, u32) {
+        let Some(aspect) = aspect else {
+            return (0, 0, width, height);
+        };
 
+        let (letterbox_width, letterbox_height) = if width as f32 > height as f32 * aspect {
+            (
+                ((height as f32 * aspect).round() as u32).clamp(1, width),
+                height,
+            )
+        } else {
+            (
+                width,
+                ((width as f32 / aspect).round() as u32).clamp(1, height),
+            )
+        };
+        (
+            (width - letterbox_width) / 2,
+            (height - letterbox_height) / 2,
+            letterbox_width,
+            letterbox_height,
+        )
+    }
+
+    // Splits the letterboxed target into equal side by side columns, one per camera
+    fn split_viewports(
+        target_size: (u32, u32),
+        letterbox_aspect: Option<f32>,
+        count: usize,
+    ) -> Vec<Option<pipeline::Viewport>> {
+        if count <= 1 && letterbox_aspect.is_none() {
+            return vec![None];
+        }
+
+        // The first viewport clears the whole target, which leaves the bars
+        // around the letterbox in the clear color
+        let (x, y, width, height) = Self::letterbox(target_size, letterbox_aspect);
+        let count = count.max(1) as u32;
+        let viewport_width = (width / count).max(1);
+        (0..count)
+            .map(|i| {
+                Some(pipeline::Viewport {
+                    x: x + i * viewport_width,
+                    y,
+                    width: viewport_width,
+                    height,
+                    clear: i == 0,
+                })
+            })
+            .collect()
+    }
+
+    // Maps a window position onto the depth texture, which may be scaled
+    fn set_depth_probe(&mut self, cursor: Option<(f32, f32)>) {
+        let (width, height) = self.window.inner_size();
+        let texel = cursor.map(|(x, y)| {
+            let scale_x = self.depth_texture.width() as f32 / width.max(1) as f32;
+            let scale_y = self.depth_texture.height() as f32 / height.max(1) as f32;
+            ((x * scale_x) as u32, (y * scale_y) as u32)
+        });
+        self.depth_readback.set_texel(texel);
+    }
+
+    fn cursor_point(&self, scene: &Scene, cursor: (f32, f32)) -> Option<cgmath::Point3<f32>> {
+        let depth = self.depth_readback.depth()?;
+        let far_depth = if self.reverse_z { 0.0 } else { 1.0 };
+        if depth == far_depth {
+            return None;
+        }
+
+        let (cursor, size) = self.view_cursor(cursor);
+        Some(scene.camera.unproject(cursor, size, depth))
+    }
+
+    // Moves a window position into the letterboxed view and returns its size
+    fn view_cursor(&self, (x, y): (f32, f32)) -> ((f32, f32), (u32, u32)) {
+        let (left, top, width, height) =
+            Self::letterbox(self.window.inner_size(), self.letterbox_aspect);
+        ((x - left as f32, y - top as f32), (width, height))
+    }
+
+    pub fn render(&mut self, scene: &mut Scene) -> Result<(), wgpu::SurfaceError> {
         if !self.is_surface_configured {
             return Ok(());
         }
 
+        if let Err(err) = self.device.poll(wgpu::PollType::Poll) {
+            error!("failed to poll the device: {}", err);
+        }
+        self.depth_readback.poll();
+        if let Some(pass_timer) = &mut self.pass_timer {
+            pass_timer.poll();
+        }
+
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -187,6 +1030,9 @@ impl<'window> RendererState<'window> {
             });
 
         let surface_texture = self.surface.get_current_texture()?;
+        if let Some(pass_timer) = &mut self.pass_timer {
+            pass_timer.begin(&mut encoder);
+        }
         let surface_view_descriptor = wgpu::TextureViewDescriptor {
             label: Some("Surface Texture View"),
             format: Some(self.surface_config.format),
@@ -199,37 +1045,638 @@ impl<'window> RendererState<'window> {
             .texture
             .create_view(&surface_view_descriptor);
 
-        self.mesh_pipeline.update(scene, &self.device, &self.queue);
-        self.mesh_pipeline.draw(
-            &self.device,
-            &mut encoder,
-            &surface_view,
-            &self.depth_texture_view,
-        );
+        let output_view = self
+            .scaled_texture
+            .as_ref()
+            .map_or(&surface_view, |(_, scaled_texture_view)| {
+                scaled_texture_view
+            });
+        let (view, resolve_target) = match (self.anti_aliasing, &self.color_texture) {
+            (AntiAliasing::Msaa(_), Some((_, color_texture_view))) => {
+                (color_texture_view, Some(output_view))
+            }
+            (_, Some((_, color_texture_view))) => (color_texture_view, None),
+            (_, None) => (output_view, None),
+        };
+
+        let target_size = (self.depth_texture.width(), self.depth_texture.height());
+        let far_depth = if self.reverse_z { 0.0 } else { 1.0 };
+        let viewports =
+            Self::split_viewports(target_size, self.letterbox_aspect, scene.cameras.len() + 1);
+        for (i, viewport) in viewports.into_iter().enumerate() {
+            if i > 0 {
+                // Each viewport writes its own camera, so the previous one has to be
+                // submitted before the buffers are overwritten
+                let previous = std::mem::replace(
+                    &mut encoder,
+                    self.device
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Command Encoder"),
+                        }),
+                );
+                self.queue.submit([previous.finish()]);
+                std::mem::swap(&mut *scene.camera, &mut scene.cameras[i - 1]);
+            }
+            // The camera buffer holds whichever viewport was drawn last
+            if viewport.is_some() {
+                scene.camera.mark_dirty();
+            }
+            let (width, height) =
+                viewport.map_or(target_size, |viewport| (viewport.width, viewport.height));
+            let aspect = width as f32 / height as f32;
+            if scene.camera.aspect != aspect {
+                scene.camera.set_aspect(aspect);
+            }
+
+            match self.mesh_pipeline.as_mut() {
+                Some(mesh_pipeline) => {
+                    mesh_pipeline.set_viewport(&self.device, viewport);
+                    mesh_pipeline.update(scene, &self.device, &self.queue);
+                    mesh_pipeline.draw(
+                        &self.device,
+                        &mut encoder,
+                        view,
+                        resolve_target,
+                        &self.depth_texture_view,
+                        pipeline::viewport_load_op(viewport, far_depth),
+                    );
+                }
+                None => {
+                    let render_pass_descriptor = wgpu::RenderPassDescriptor {
+                        label: Some("Loading Render Pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view,
+                            depth_slice: None,
+                            resolve_target,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(pipeline::clear_color(
+                                    self.surface_config.format,
+                                )),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        ..Default::default()
+                    };
+                    encoder.begin_render_pass(&render_pass_descriptor);
+                }
+            }
+            if let Some(pass_timer) = &mut self.pass_timer {
+                pass_timer.mark(&mut encoder, "mesh");
+            }
+            if scene.show_bounds {
+                self.bounds_pipeline
+                    .update(&self.device, &self.queue, scene);
+                self.bounds_pipeline
+                    .draw(&mut encoder, view, resolve_target, viewport);
+                if let Some(pass_timer) = &mut self.pass_timer {
+                    pass_timer.mark(&mut encoder, "bounds");
+                }
+            }
+
+            if i > 0 {
+                std::mem::swap(&mut *scene.camera, &mut scene.cameras[i - 1]);
+            }
+        }
+        if let Some(gizmo_pipeline) = &self.gizmo_pipeline {
+            gizmo_pipeline.update(&self.queue, &scene.camera);
+            gizmo_pipeline.draw(
+                &mut encoder,
+                view,
+                resolve_target,
+                (self.depth_texture.width(), self.depth_texture.height()),
+            );
+            if let Some(pass_timer) = &mut self.pass_timer {
+                pass_timer.mark(&mut encoder, "gizmo");
+            }
+        }
+        if let Some(fxaa_pipeline) = &self.fxaa_pipeline {
+            fxaa_pipeline.draw(&mut encoder, &surface_view);
+            if let Some(pass_timer) = &mut self.pass_timer {
+                pass_timer.mark(&mut encoder, "fxaa");
+            }
+        }
+        if let (Some(resample_pipeline), Some(_)) = (&self.resample_pipeline, &self.scaled_texture)
+        {
+            resample_pipeline.draw(&mut encoder, &surface_view);
+            if let Some(pass_timer) = &mut self.pass_timer {
+                pass_timer.mark(&mut encoder, "resample");
+            }
+        }
+        self.depth_readback.copy(&mut encoder, &self.depth_texture);
+        if let Some(pass_timer) = &mut self.pass_timer {
+            pass_timer.resolve(&mut encoder);
+        }
         let buffer = encoder.finish();
         self.queue.submit(vec![buffer]);
+        self.depth_readback.map();
+        if let Some(pass_timer) = &mut self.pass_timer {
+            pass_timer.map();
+        }
         surface_texture.present();
         Ok(())
     }
 }
 
+#[derive(Clone)]
+pub struct SceneCommandSender {
+    sender: mpsc::Sender<SceneCommand>,
+    proxy: EventLoopProxy<()>,
+}
+
+impl SceneCommandSender {
+    pub fn send(&self, command: SceneCommand) -> Result<(), mpsc::SendError<SceneCommand>> {
+        self.sender.send(command)?;
+        let _ = self.proxy.send_event(());
+        Ok(())
+    }
+}
+
+const DRAG_GRID: f32 = 0.5;
+const PIXELS_PER_LINE: f32 = 40.0;
+
+#[derive(Clone, Copy, Debug)]
+struct Drag {
+    object: usize,
+    height: f32,
+    offset: (f32, f32),
+}
+
+// Files that aren't PLY or STL are read as OBJ
+fn load_model_file(path: &Path, options: &ModelLoadOptions) -> Result<Model, anyhow::Error> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("ply") => {
+            let mut model = scene::load_ply(path)?;
+            if options.deduplicate_vertices {
+                model.deduplicate_vertices();
+            }
+            Ok(model)
+        }
+        Some(extension) if extension.eq_ignore_ascii_case("stl") => Ok(scene::load_stl(path)?),
+        _ => Ok(Model::load_obj(path, options)?),
+    }
+}
+
+fn intersect_height(
+    origin: cgmath::Point3<f32>,
+    direction: cgmath::Vector3<f32>,
+    height: f32,
+) -> Option<cgmath::Point3<f32>> {
+    if direction.y.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let t = (height - origin.y) / direction.y;
+    if t < 0.0 {
+        return None;
+    }
+    Some(origin + direction * t)
+}
+
+const BENCHMARK_FRAMES: usize = 1000;
+const BENCHMARK_SPACING: f32 = 3.0;
+const BENCHMARK_ORBIT_RATE: f32 = 0.5;
+
+struct Benchmark {
+    frame_times: Vec<f32>,
+}
+
+impl Benchmark {
+    fn report(&self) {
+        if self.frame_times.is_empty() {
+            info!("benchmark: no frames recorded");
+            return;
+        }
+
+        let count = self.frame_times.len() as f32;
+        let min = self
+            .frame_times
+            .iter()
+            .copied()
+            .fold(f32::INFINITY, f32::min);
+        let max = self.frame_times.iter().copied().fold(0.0, f32::max);
+        let avg = self.frame_times.iter().sum::<f32>() / count;
+        info!(
+            "benchmark: {} frames, min {:.3} ms, avg {:.3} ms, max {:.3} ms",
+            self.frame_times.len(),
+            min * 1000.0,
+            avg * 1000.0,
+            max * 1000.0
+        );
+    }
+}
+
 pub struct App<'window> {
     state: Option<RendererState<'window>>,
     scene: Option<Scene>,
-    kmap: HashMap<PhysicalKey, bool>,
+    pending_scene: Option<(Scene, InitData)>,
+    load_state: LoadState,
+    model_loader: Option<ModelLoader>,
+    lay_out_models: bool,
+    load_options: ModelLoadOptions,
+    commands: Option<(mpsc::Sender<SceneCommand>, mpsc::Receiver<SceneCommand>)>,
+    options: RendererOptions,
+    title: String,
+    size: winit::dpi::LogicalSize<f64>,
+    maximized: bool,
+    fullscreen: FullscreenMode,
+    video_modes: Option<Vec<VideoMode>>,
+    keys: KeyState,
+    buttons: HashMap<MouseButton, bool>,
     mouse_movements: Vec<(f32, f32)>,
+    scroll: f32,
+    cursor_position: (f32, f32),
+    modifiers: ModifiersState,
+    drag: Option<Drag>,
+    drag_grid: f32,
     delta: f32,
+    fps_cap: Option<u32>,
+    focused: bool,
+    unfocused_mode: UnfocusedMode,
+    benchmark: Option<Benchmark>,
+}
+
+impl<'window> Default for App<'window> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'window> App<'window> {
     pub fn new() -> App<'window> {
-        return App {
+        App {
             state: None,
             scene: None,
-            kmap: HashMap::new(),
+            pending_scene: None,
+            load_state: LoadState::Uninitialized,
+            model_loader: None,
+            lay_out_models: false,
+            load_options: ModelLoadOptions::default(),
+            commands: None,
+            options: RendererOptions::default(),
+            title: String::from("WGPU Sandbox"),
+            size: winit::dpi::LogicalSize::new(1280.0, 720.0),
+            maximized: true,
+            fullscreen: FullscreenMode::default(),
+            video_modes: None,
+            keys: KeyState::default(),
+            buttons: HashMap::new(),
             mouse_movements: Vec::new(),
+            scroll: 0.0,
+            cursor_position: (0.0, 0.0),
+            modifiers: ModifiersState::empty(),
+            drag: None,
+            drag_grid: DRAG_GRID,
             delta: 0.0069,
+            benchmark: None,
+            fps_cap: None,
+            focused: true,
+            unfocused_mode: UnfocusedMode::default(),
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn with_size(mut self, width: f64, height: f64) -> Self {
+        self.size = winit::dpi::LogicalSize::new(width, height);
+        self
+    }
+
+    pub fn with_maximized(mut self, maximized: bool) -> Self {
+        self.maximized = maximized;
+        self
+    }
+
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.options.anti_aliasing = anti_aliasing;
+    }
+
+    pub fn set_shading(&mut self, shading: pipeline::deferred::Shading) {
+        self.options.shading = shading;
+    }
+
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        self.options.reverse_z = reverse_z;
+    }
+
+    pub fn set_point_shadow_resolution(&mut self, resolution: u32) {
+        self.options.point_shadow_resolution = resolution;
+    }
+
+    pub fn set_shadow_cascades(&mut self, cascade_count: u32, split_lambda: f32) {
+        self.options.cascade_count = cascade_count;
+        self.options.cascade_split_lambda = split_lambda;
+    }
+
+    pub fn set_directional_shadow_count(&mut self, shadow_count: u32) {
+        self.options.directional_shadow_count = shadow_count;
+    }
+
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.options.render_scale = render_scale;
+    }
+
+    pub fn set_dynamic_resolution(&mut self, dynamic_resolution: Option<DynamicResolutionOptions>) {
+        self.options.dynamic_resolution = dynamic_resolution;
+    }
+
+    pub fn set_point_size(&mut self, point_size: f32) {
+        self.options.point_size = point_size;
+    }
+
+    pub fn set_depth_bias(&mut self, depth_bias: wgpu::DepthBiasState) {
+        self.options.depth_bias = depth_bias;
+    }
+
+    pub fn set_layer_state(
+        &mut self,
+        layer: pipeline::mesh::Layer,
+        layer_state: pipeline::mesh::LayerState,
+    ) {
+        self.options.layer_states[layer as usize] = layer_state;
+    }
+
+    pub fn set_background(&mut self, background: Option<pipeline::background::Gradient>) {
+        self.options.background = background;
+    }
+
+    pub fn set_letterbox_aspect(&mut self, letterbox_aspect: Option<f32>) {
+        self.options.letterbox_aspect = letterbox_aspect;
+    }
+
+    pub fn set_pipeline_cache_dir(&mut self, pipeline_cache_dir: Option<PathBuf>) {
+        self.options.pipeline_cache_dir = pipeline_cache_dir;
+    }
+
+    pub fn set_upscale_filter(&mut self, upscale_filter: wgpu::FilterMode) {
+        self.options.upscale_filter = upscale_filter;
+    }
+
+    pub fn set_anisotropy(&mut self, anisotropy: u16) {
+        self.options.anisotropy = anisotropy;
+    }
+
+    pub fn set_gizmo(&mut self, gizmo: Option<pipeline::gizmo::GizmoOptions>) {
+        self.options.gizmo = gizmo;
+    }
+
+    pub fn set_memory_hints(&mut self, memory_hints: wgpu::MemoryHints) {
+        self.options.memory_hints = memory_hints;
+    }
+
+    pub fn set_backends(&mut self, backends: wgpu::Backends) {
+        self.options.backends = backends;
+    }
+
+    pub fn set_adapter(&mut self, adapter: Option<AdapterSelection>) {
+        self.options.adapter = adapter;
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: FullscreenMode) {
+        self.fullscreen = fullscreen;
+    }
+
+    // Monitors can only be queried from a running event loop, so there are no
+    // modes until the app has first resumed
+    pub fn video_modes(&self) -> Option<&[VideoMode]> {
+        self.video_modes.as_deref()
+    }
+
+    fn window_fullscreen(&mut self, event_loop: &ActiveEventLoop) -> Option<Fullscreen> {
+        let video_modes = collect_video_modes(event_loop.available_monitors());
+        self.video_modes = Some(video_modes.iter().map(|(mode, _)| *mode).collect());
+
+        match self.fullscreen {
+            FullscreenMode::Windowed => None,
+            FullscreenMode::Borderless => Some(Fullscreen::Borderless(None)),
+            FullscreenMode::Exclusive(mode) => {
+                let video_mode = video_modes
+                    .into_iter()
+                    .find(|(available, _)| *available == mode);
+                match video_mode {
+                    Some((_, video_mode)) => Some(Fullscreen::Exclusive(video_mode)),
+                    None => {
+                        warn!(
+                            "video mode {:?} is not available, falling back to borderless",
+                            mode
+                        );
+                        for available in self.video_modes.iter().flatten() {
+                            info!("available video mode: {:?}", available);
+                        }
+                        let monitor = event_loop.available_monitors().nth(mode.monitor);
+                        Some(Fullscreen::Borderless(monitor))
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn set_fps_cap(&mut self, fps_cap: Option<u32>) {
+        self.fps_cap = fps_cap;
+    }
+
+    pub fn set_unfocused_mode(&mut self, unfocused_mode: UnfocusedMode) {
+        self.unfocused_mode = unfocused_mode;
+    }
+
+    pub fn set_drag_grid(&mut self, drag_grid: f32) {
+        self.drag_grid = drag_grid;
+    }
+
+    pub fn set_model_loader(
+        &mut self,
+        loader: impl FnOnce() -> Result<InitData, anyhow::Error> + Send + 'static,
+    ) {
+        self.model_loader = Some(Box::new(loader));
+    }
+
+    pub fn set_load_options(&mut self, load_options: ModelLoadOptions) {
+        self.load_options = load_options;
+    }
+
+    pub fn set_assets(&mut self, directory: impl Into<PathBuf>, models: Vec<String>) {
+        let directory = directory.into();
+        let load_options = self.load_options;
+        self.set_model_loader(move || {
+            let models = models
+                .iter()
+                .filter_map(|name| {
+                    let path = directory.join(name);
+                    load_model_file(&path, &load_options)
+                        .inspect_err(|err| error!("failed to load {}: {}", path.display(), err))
+                        .ok()
+                })
+                .collect();
+            Ok(InitData { models })
+        });
+        self.lay_out_models = true;
+    }
+
+    pub fn set_scene(&mut self, mut scene: Scene, init_data: InitData) {
+        scene.mark_dirty();
+        let Some(state) = self.state.as_mut() else {
+            self.pending_scene = Some((scene, init_data));
+            return;
+        };
+
+        state.load(&init_data, &mut scene);
+        let (width, height) = state.window.inner_size();
+        state.resize(width, height, Some(&mut scene));
+        self.scene = Some(scene);
+        self.load_state = LoadState::Ready;
+    }
+
+    // Renders a grid of cubes for a fixed number of frames, then logs the frame
+    // times at info level and exits
+    pub fn benchmark(&mut self, object_count: usize) {
+        let model = load_model!("../assets/cube.obj").unwrap();
+        let side = (object_count as f32).sqrt().ceil().max(1.0);
+        let offset = (side - 1.0) * BENCHMARK_SPACING / 2.0;
+        let objects = (0..object_count)
+            .map(|i| {
+                let (x, z) = (i as f32 % side, (i as f32 / side).floor());
+                let translation = cgmath::Vector3::new(
+                    x * BENCHMARK_SPACING - offset,
+                    0.0,
+                    z * BENCHMARK_SPACING - offset,
+                );
+                mesh::Object::from_transform(cgmath::Matrix4::from_translation(translation), 0.5)
+            })
+            .collect();
+
+        let distance = side * BENCHMARK_SPACING;
+        let mut scene = Scene::new(1.0, cgmath::Point3::new(0.0, distance * 0.5, -distance));
+        scene.set_objects(objects);
+        // Every object draws the one cube, so its buffers are only uploaded once
+        scene.object_models = vec![0; object_count];
+        scene.set_camera_mode(CameraMode::Orbit);
+        let init_data = InitData {
+            models: vec![model],
+        };
+
+        self.set_scene(scene, init_data);
+        self.benchmark = Some(Benchmark {
+            frame_times: Vec::with_capacity(BENCHMARK_FRAMES),
+        });
+    }
+
+    pub fn scene_command_sender(&mut self, event_loop: &EventLoop<()>) -> SceneCommandSender {
+        let (sender, _) = self.commands.get_or_insert_with(mpsc::channel);
+        SceneCommandSender {
+            sender: sender.clone(),
+            proxy: event_loop.create_proxy(),
+        }
+    }
+
+    fn apply_scene_commands(&mut self) {
+        if let (Some((_, receiver)), Some(scene)) = (&self.commands, self.scene.as_mut()) {
+            for command in receiver.try_iter() {
+                scene.apply(command);
+            }
+        }
+    }
+
+    pub fn cursor_point(&self) -> Option<cgmath::Point3<f32>> {
+        let (state, scene) = (self.state.as_ref()?, self.scene.as_ref()?);
+        state.cursor_point(scene, self.cursor_position)
+    }
+
+    // Per-pass GPU durations in milliseconds; None when timestamp queries are unsupported
+    pub fn pass_timings(&self) -> Option<&[(&'static str, f32)]> {
+        let pass_timer = self.state.as_ref()?.pass_timer.as_ref()?;
+        Some(pass_timer.timings())
+    }
+
+    // Distance along the view direction to the surface under the cursor
+    pub fn cursor_depth(&self) -> Option<f32> {
+        let point = self.cursor_point()?;
+        let scene = self.scene.as_ref()?;
+        Some(scene.camera.view.transform_point(point).z)
+    }
+
+    fn cursor_ray(&self) -> Option<(cgmath::Point3<f32>, cgmath::Vector3<f32>)> {
+        let (state, scene) = (self.state.as_ref()?, self.scene.as_ref()?);
+        let (cursor, size) = state.view_cursor(self.cursor_position);
+        Some(scene.camera.screen_ray(cursor, size))
+    }
+
+    fn begin_drag(&mut self) {
+        let (Some((origin, direction)), Some(scene)) = (self.cursor_ray(), self.scene.as_mut())
+        else {
+            return;
+        };
+        scene.selected = scene.pick(origin, direction);
+        let Some(object) = scene.selected else {
+            return;
+        };
+
+        let model = scene.objects.values()[object].model;
+        let height = model[3][1];
+        if let Some(point) = intersect_height(origin, direction, height) {
+            self.drag = Some(Drag {
+                object,
+                height,
+                offset: (model[3][0] - point.x, model[3][2] - point.z),
+            });
+        }
+    }
+
+    fn update_drag(&mut self) {
+        let (Some(drag), Some((origin, direction))) = (self.drag, self.cursor_ray()) else {
+            return;
+        };
+        let Some(point) = intersect_height(origin, direction, drag.height) else {
+            return;
+        };
+
+        let (mut x, mut z) = (point.x + drag.offset.0, point.z + drag.offset.1);
+        if self.modifiers.control_key() && self.drag_grid > 0.0 {
+            x = (x / self.drag_grid).round() * self.drag_grid;
+            z = (z / self.drag_grid).round() * self.drag_grid;
+        }
+        if let Some(scene) = self.scene.as_mut()
+            && let Some(mut transform) = scene.transforms.get(drag.object).copied()
+        {
+            transform.translation.x = x;
+            transform.translation.z = z;
+            scene.set_transform(drag.object, transform);
+        }
+        self.request_redraw();
+    }
+
+    fn request_redraw(&self) {
+        if let Some(state) = &self.state {
+            state.window.request_redraw();
+        }
+    }
+
+    fn poll_loading(&mut self) {
+        let LoadState::Loading(receiver) = &self.load_state else {
+            return;
+        };
+
+        let init_data = match receiver.try_recv() {
+            Ok(Ok(init_data)) => init_data,
+            Ok(Err(err)) => {
+                error!("failed to load models: {}", err);
+                InitData { models: vec![] }
+            }
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                error!("model loader exited without producing any models");
+                InitData { models: vec![] }
+            }
         };
+
+        if let (Some(state), Some(scene)) = (self.state.as_mut(), self.scene.as_mut()) {
+            if self.lay_out_models {
+                scene.lay_out_objects(init_data.models.len());
+            }
+            state.load(&init_data, scene);
+            self.load_state = LoadState::Ready;
+        }
     }
 }
 
@@ -237,30 +1684,57 @@ impl<'window> ApplicationHandler for App<'window> {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.state.is_none() {
             let attrs = Window::default_attributes()
-                .with_title("WGPU Sandbox")
-                .with_inner_size(winit::dpi::LogicalSize::new(1280.0, 720.0))
-                .with_maximized(true)
-                .with_resizable(true);
+                .with_title(self.title.clone())
+                .with_inner_size(self.size)
+                .with_maximized(self.maximized)
+                .with_resizable(true)
+                .with_fullscreen(self.window_fullscreen(event_loop));
             match event_loop.create_window(attrs) {
                 Err(err) => {
                     error!("failed to create window {}", err);
                     event_loop.exit();
                 }
                 Ok(window) => {
-                    let init_data = InitData {
-                        models: vec![
-                            load_model!("../assets/cube.obj").unwrap(),
-                            load_model!("../assets/monkey.obj").unwrap(),
-                            load_model!("../assets/plane.obj").unwrap(),
-                        ],
-                    };
-                    let scene = Scene::new(
+                    if matches!(self.fullscreen, FullscreenMode::Exclusive(_))
+                        && window.fullscreen().is_none()
+                    {
+                        warn!("exclusive fullscreen was refused, falling back to borderless");
+                        window
+                            .set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())));
+                    }
+
+                    let mut scene = Scene::new(
                         (window.inner_size().width as f32) / (window.inner_size().height as f32),
                         cgmath::Point3::new(0.0, 1.2, -3.0),
                     );
-                    let state = RendererState::new(Arc::new(window), &scene, &init_data).block_on();
+                    let state = RendererState::new(Arc::new(window), &self.options).block_on();
                     match state {
-                        Ok(state) => {
+                        Ok(mut state) => {
+                            match (self.pending_scene.take(), self.model_loader.take()) {
+                                (Some((pending_scene, init_data)), _) => {
+                                    scene = pending_scene;
+                                    state.load(&init_data, &mut scene);
+                                    self.load_state = LoadState::Ready;
+                                }
+                                (None, Some(loader)) => {
+                                    let (sender, receiver) = mpsc::channel();
+                                    thread::spawn(move || {
+                                        let _ = sender.send(loader());
+                                    });
+                                    self.load_state = LoadState::Loading(receiver);
+                                }
+                                (None, None) => {
+                                    let init_data = InitData {
+                                        models: vec![
+                                            load_model!("../assets/cube.obj").unwrap(),
+                                            load_model!("../assets/monkey.obj").unwrap(),
+                                            load_model!("../assets/plane.obj").unwrap(),
+                                        ],
+                                    };
+                                    state.load(&init_data, &mut scene);
+                                    self.load_state = LoadState::Ready;
+                                }
+                            }
                             self.state = Some(state);
                             self.scene = Some(scene);
                         }
@@ -274,6 +1748,10 @@ impl<'window> ApplicationHandler for App<'window> {
         }
     }
 
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, _event: ()) {
+        self.request_redraw();
+    }
+
     fn device_event(
         &mut self,
         _event_loop: &ActiveEventLoop,
@@ -281,8 +1759,9 @@ impl<'window> ApplicationHandler for App<'window> {
         event: winit::event::DeviceEvent,
     ) {
         match event {
-            winit::event::DeviceEvent::MouseMotion { delta } => {
+            winit::event::DeviceEvent::MouseMotion { delta } if self.drag.is_none() => {
                 self.mouse_movements.push((delta.0 as f32, delta.1 as f32));
+                self.request_redraw();
             }
             _ => {}
         }
@@ -298,7 +1777,6 @@ impl<'window> ApplicationHandler for App<'window> {
         match event {
             WindowEvent::CloseRequested => {
                 event_loop.exit();
-                return;
             }
             WindowEvent::RedrawRequested => {
                 instant = time::Instant::now();
@@ -306,34 +1784,99 @@ impl<'window> ApplicationHandler for App<'window> {
                     return;
                 }
 
+                self.apply_scene_commands();
+                self.poll_loading();
+                if self.benchmark.is_some() {
+                    self.scene
+                        .as_mut()
+                        .unwrap()
+                        .rotate_orbit(BENCHMARK_ORBIT_RATE * self.delta);
+                }
                 self.scene.as_mut().unwrap().update(
-                    &self.kmap,
+                    &mut self.keys,
+                    &self.buttons,
                     &mut self.mouse_movements,
+                    &mut self.scroll,
                     self.delta,
                 );
+                // A benchmark always runs at full rate
+                let paused = !self.focused
+                    && self.unfocused_mode == UnfocusedMode::Pause
+                    && self.benchmark.is_none();
+                let active = !paused
+                    && (self.scene.as_ref().unwrap().is_dirty()
+                        || matches!(self.load_state, LoadState::Loading(_))
+                        || self.benchmark.is_some());
                 match self
                     .state
-                    .as_ref()
+                    .as_mut()
                     .unwrap()
                     .render(self.scene.as_mut().unwrap())
                 {
                     Ok(_) => {}
                     Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
                         let state = self.state.as_mut().unwrap();
-                        state.resize(
-                            state.window.inner_size().width,
-                            state.window.inner_size().height,
-                            self.scene.as_mut(),
-                        );
+                        let (width, height) = state.window.inner_size();
+                        state.resize(width, height, self.scene.as_mut());
+                    }
+                    Err(wgpu::SurfaceError::Timeout) => {
+                        warn!("timed out acquiring the surface texture, skipping the frame");
+                        self.request_redraw();
+                    }
+                    Err(wgpu::SurfaceError::OutOfMemory) => {
+                        error!("out of memory while acquiring the surface texture, exiting");
+                        event_loop.exit();
+                        return;
                     }
                     Err(err) => {
                         error!("an error occured while rendering: {}", err);
                     }
                 }
 
+                let fps_cap = match (self.focused, self.unfocused_mode) {
+                    (false, UnfocusedMode::Throttle(throttle)) if self.benchmark.is_none() => Some(
+                        self.fps_cap
+                            .map_or(throttle, |fps_cap| fps_cap.min(throttle)),
+                    ),
+                    _ => self.fps_cap,
+                };
+                if let Some(fps_cap) = fps_cap.filter(|fps_cap| *fps_cap > 0) {
+                    let frame_time = time::Duration::from_secs_f32(1.0 / fps_cap as f32);
+                    let elapsed = instant.elapsed();
+                    if elapsed < frame_time {
+                        thread::sleep(frame_time - elapsed);
+                    }
+                }
+
                 let current = time::Instant::now();
                 let delta_duration = current.duration_since(instant);
                 self.delta = delta_duration.as_secs_f32();
+                self.state
+                    .as_mut()
+                    .unwrap()
+                    .update_dynamic_resolution(self.delta, self.scene.as_mut());
+
+                if let Some(benchmark) = self.benchmark.as_mut() {
+                    benchmark.frame_times.push(self.delta);
+                    if benchmark.frame_times.len() >= BENCHMARK_FRAMES {
+                        benchmark.report();
+                        event_loop.exit();
+                        return;
+                    }
+                }
+
+                if active {
+                    event_loop.set_control_flow(ControlFlow::Poll);
+                    self.request_redraw();
+                } else {
+                    event_loop.set_control_flow(ControlFlow::Wait);
+                }
+            }
+            WindowEvent::Focused(focused) => {
+                self.focused = focused;
+                if focused {
+                    self.request_redraw();
+                }
             }
             WindowEvent::Resized(new_size) => {
                 if self.state.is_none() {
@@ -343,19 +1886,88 @@ impl<'window> ApplicationHandler for App<'window> {
                 let state = self.state.as_mut().unwrap();
                 state.resize(new_size.width, new_size.height, self.scene.as_mut());
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = (position.x as f32, position.y as f32);
+                if let Some(state) = self.state.as_mut() {
+                    state.set_depth_probe(Some(self.cursor_position));
+                }
+                self.update_drag();
+            }
+            WindowEvent::CursorLeft { .. } => {
+                if let Some(state) = self.state.as_mut() {
+                    state.set_depth_probe(None);
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.buttons.insert(button, state.is_pressed());
+                match (button, state) {
+                    (MouseButton::Left, winit::event::ElementState::Pressed) => self.begin_drag(),
+                    (MouseButton::Left, winit::event::ElementState::Released) => self.drag = None,
+                    _ => {}
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                self.request_redraw();
+            }
+            WindowEvent::DroppedFile(path) => {
+                let model = match path.extension().and_then(|extension| extension.to_str()) {
+                    Some(extension)
+                        if extension.eq_ignore_ascii_case("obj")
+                            || extension.eq_ignore_ascii_case("ply")
+                            || extension.eq_ignore_ascii_case("stl") =>
+                    {
+                        load_model_file(&path, &self.load_options)
+                    }
+                    _ => Err(anyhow::anyhow!("unsupported model format")),
+                };
+                match (model, self.state.as_mut(), self.scene.as_mut()) {
+                    (Ok(model), Some(state), Some(scene)) => {
+                        state.add_model(&model, scene);
+                        self.request_redraw();
+                    }
+                    (Err(err), _, _) => error!("failed to load {}: {}", path.display(), err),
+                    _ => {}
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
             WindowEvent::KeyboardInput {
                 device_id: _,
                 event,
                 is_synthetic: _,
-            } => match event.state {
-                winit::event::ElementState::Pressed => {
-                    self.kmap.insert(event.physical_key.clone(), true);
-                }
-                winit::event::ElementState::Released => {
-                    self.kmap.insert(event.physical_key.clone(), false);
+            } => {
+                match event.state {
+                    winit::event::ElementState::Pressed => {
+                        self.keys.press(event.physical_key, event.repeat);
+                        if let (false, PhysicalKey::Code(KeyCode::KeyV), Some(state)) =
+                            (event.repeat, event.physical_key, self.state.as_mut())
+                        {
+                            state.cycle_present_mode();
+                        }
+                        if let (false, PhysicalKey::Code(KeyCode::KeyF), Some(point)) =
+                            (event.repeat, event.physical_key, self.cursor_point())
+                        {
+                            self.scene.as_mut().unwrap().set_focus(point);
+                        }
+                    }
+                    winit::event::ElementState::Released => {
+                        self.keys.release(event.physical_key);
+                    }
                 }
-            },
+                self.request_redraw();
+            }
             _ => (),
         }
     }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(state) = &self.state {
+            state.save_pipeline_cache();
+        }
+    }
 }
@@ -1,18 +1,164 @@
 use std::process;
-use wgpu_sandbox::App;
+use wgpu_sandbox::pipeline::background::Gradient;
+use wgpu_sandbox::scene::ModelLoadOptions;
+use wgpu_sandbox::{AdapterSelection, App, DynamicResolutionOptions, FullscreenMode};
 
+use anyhow::{Context, bail};
 use log::error;
 use winit::event_loop::{ControlFlow, EventLoop};
 
+const DEFAULT_WIDTH: f64 = 1280.0;
+const DEFAULT_HEIGHT: f64 = 720.0;
+
+fn parse_dimension(name: &str, value: Option<String>) -> Result<f64, anyhow::Error> {
+    let value = value.with_context(|| format!("{} requires a value", name))?;
+    let dimension = value
+        .parse::<f64>()
+        .with_context(|| format!("invalid value for {}: {}", name, value))?;
+    if dimension <= 0.0 {
+        bail!("{} must be positive, got {}", name, value);
+    }
+    Ok(dimension)
+}
+
+// Accepts either a ratio like 16:9 or a single number like 2.39
+fn parse_aspect(value: Option<String>) -> Result<f32, anyhow::Error> {
+    let value = value.context("--letterbox requires an aspect ratio")?;
+    let aspect = match value.split_once(':') {
+        Some((width, height)) => {
+            parse_dimension("--letterbox", Some(width.to_string()))?
+                / parse_dimension("--letterbox", Some(height.to_string()))?
+        }
+        None => parse_dimension("--letterbox", Some(value))?,
+    };
+    Ok(aspect as f32)
+}
+
+// Linear color components, like 0.1,0.2,0.3
+fn parse_color(name: &str, value: Option<String>) -> Result<[f32; 3], anyhow::Error> {
+    let value = value.with_context(|| format!("{} requires two colors", name))?;
+    let components = value
+        .split(',')
+        .map(|component| component.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+        .and_then(|components| <[f32; 3]>::try_from(components).ok());
+    components.with_context(|| format!("invalid color for {}: {}", name, value))
+}
+
+// A constant and a slope scale, like 2,1.5
+fn parse_depth_bias(value: Option<String>) -> Result<wgpu::DepthBiasState, anyhow::Error> {
+    let value = value.context("--depth-bias requires a constant and a slope scale")?;
+    let bias = value.split_once(',').and_then(|(constant, slope_scale)| {
+        Some(wgpu::DepthBiasState {
+            constant: constant.trim().parse().ok()?,
+            slope_scale: slope_scale.trim().parse().ok()?,
+            clamp: 0.0,
+        })
+    });
+    bias.with_context(|| format!("invalid value for --depth-bias: {}", value))
+}
+
+fn parse_backends(value: Option<String>) -> Result<wgpu::Backends, anyhow::Error> {
+    let value = value.context("--backends requires a value")?;
+    let backends = match value.as_str() {
+        "all" => wgpu::Backends::all(),
+        "primary" => wgpu::Backends::PRIMARY,
+        list => wgpu::Backends::from_comma_list(list),
+    };
+    if backends.is_empty() {
+        bail!("invalid value for --backends: {}", value);
+    }
+    Ok(backends)
+}
+
+fn parse_anisotropy(value: Option<String>) -> Result<u16, anyhow::Error> {
+    let value = value.context("--anisotropy requires a value")?;
+    match value.parse::<u16>() {
+        Ok(anisotropy @ (1 | 2 | 4 | 8 | 16)) => Ok(anisotropy),
+        _ => bail!(
+            "invalid value for --anisotropy: {}, expected 1, 2, 4, 8 or 16",
+            value
+        ),
+    }
+}
+
+fn parse_adapter(value: Option<String>) -> Result<AdapterSelection, anyhow::Error> {
+    let value = value.context("--adapter requires an index or a name")?;
+    Ok(match value.parse::<usize>() {
+        Ok(index) => AdapterSelection::Index(index),
+        Err(_) => AdapterSelection::Name(value),
+    })
+}
+
+fn configure(
+    mut app: App<'static>,
+    mut args: impl Iterator<Item = String>,
+) -> Result<App<'static>, anyhow::Error> {
+    let (mut width, mut height) = (None, None);
+    let (mut assets, mut models) = (None, Vec::new());
+    let mut load_options = ModelLoadOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--assets" => assets = Some(args.next().context("--assets requires a directory")?),
+            "--flip-uv-v" => load_options.flip_uv_v = true,
+            "--dedup-vertices" => load_options.deduplicate_vertices = true,
+            "--points" => load_options.point_cloud = true,
+            "--point-size" => {
+                app.set_point_size(parse_dimension("--point-size", args.next())? as f32)
+            }
+            "--windowed" => app = app.with_maximized(false),
+            "--fullscreen" => app.set_fullscreen(FullscreenMode::Borderless),
+            "--width" => width = Some(parse_dimension("--width", args.next())?),
+            "--height" => height = Some(parse_dimension("--height", args.next())?),
+            "--adapter" => app.set_adapter(Some(parse_adapter(args.next())?)),
+            "--backends" => app.set_backends(parse_backends(args.next())?),
+            "--dynamic-resolution" => {
+                app.set_dynamic_resolution(Some(DynamicResolutionOptions::default()))
+            }
+            "--depth-bias" => app.set_depth_bias(parse_depth_bias(args.next())?),
+            "--gradient" => app.set_background(Some(Gradient {
+                top: parse_color("--gradient", args.next())?,
+                bottom: parse_color("--gradient", args.next())?,
+            })),
+            "--letterbox" => app.set_letterbox_aspect(Some(parse_aspect(args.next())?)),
+            "--nearest-upscale" => app.set_upscale_filter(wgpu::FilterMode::Nearest),
+            "--anisotropy" => app.set_anisotropy(parse_anisotropy(args.next())?),
+            "--render-scale" => {
+                app.set_render_scale(parse_dimension("--render-scale", args.next())? as f32)
+            }
+            _ if arg.starts_with("--") => bail!("unknown argument: {}", arg),
+            _ => models.push(arg),
+        }
+    }
+
+    app.set_load_options(load_options);
+    if !models.is_empty() {
+        app.set_assets(assets.unwrap_or_else(|| String::from(".")), models);
+    } else if assets.is_some() {
+        bail!("--assets requires at least one model file name");
+    }
+    if width.is_some() || height.is_some() {
+        app = app.with_size(
+            width.unwrap_or(DEFAULT_WIDTH),
+            height.unwrap_or(DEFAULT_HEIGHT),
+        );
+    }
+    Ok(app)
+}
+
 fn main() {
     env_logger::init();
+    let mut app = configure(App::new(), std::env::args().skip(1)).unwrap_or_else(|err| {
+        error!("{:#}", err);
+        process::exit(1);
+    });
     let event_loop = EventLoop::new().unwrap_or_else(|err| {
         error!("failed to create event loop: {}", err);
         process::exit(1);
     });
 
     event_loop.set_control_flow(ControlFlow::Poll);
-    let mut app = App::new();
     event_loop.run_app(&mut app).unwrap_or_else(|err| {
         error!("failed to run application: {}", err);
         process::exit(1);
@@ -1,5 +1,101 @@
+pub mod background;
+pub mod bounds;
+pub mod culling;
+pub mod deferred;
+pub mod fxaa;
+pub mod gizmo;
 pub mod mesh;
+pub mod normals;
+pub mod points;
+pub mod readback;
+pub mod resample;
+pub mod shadow;
+pub mod timestamps;
+use crate::cache::{Cache, VecCache};
 use crate::scene::{InitData, Scene};
+use thiserror::Error;
+
+// Linear color; use clear_color to get the value for a particular target.
+pub const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.003,
+    g: 0.017,
+    b: 0.032,
+    a: 1.,
+};
+
+fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn encode_color(color: wgpu::Color, format: wgpu::TextureFormat) -> wgpu::Color {
+    // sRGB targets encode on write, other targets store the value as is.
+    if format.is_srgb() {
+        return color;
+    }
+
+    wgpu::Color {
+        r: linear_to_srgb(color.r),
+        g: linear_to_srgb(color.g),
+        b: linear_to_srgb(color.b),
+        a: color.a,
+    }
+}
+
+pub fn clear_color(format: wgpu::TextureFormat) -> wgpu::Color {
+    encode_color(CLEAR_COLOR, format)
+}
+
+// A region of the render target drawn by one split-screen camera. Only the
+// first viewport of a frame clears the targets, the rest load what is there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub clear: bool,
+}
+
+pub fn viewport_load_op<V>(viewport: Option<Viewport>, clear_value: V) -> wgpu::LoadOp<V> {
+    match viewport {
+        Some(viewport) if !viewport.clear => wgpu::LoadOp::Load,
+        _ => wgpu::LoadOp::Clear(clear_value),
+    }
+}
+
+// Depth biases are given as pushing surfaces away from the camera, which is
+// toward larger depths unless the depth range is reversed
+pub fn oriented_depth_bias(bias: wgpu::DepthBiasState, reverse_z: bool) -> wgpu::DepthBiasState {
+    let direction = if reverse_z { -1 } else { 1 };
+    wgpu::DepthBiasState {
+        constant: direction * bias.constant,
+        slope_scale: direction as f32 * bias.slope_scale,
+        clamp: direction as f32 * bias.clamp,
+    }
+}
+
+pub fn set_viewport(render_pass: &mut wgpu::RenderPass, viewport: Option<Viewport>) {
+    if let Some(viewport) = viewport {
+        render_pass.set_viewport(
+            viewport.x as f32,
+            viewport.y as f32,
+            viewport.width as f32,
+            viewport.height as f32,
+            0.0,
+            1.0,
+        );
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum UploadError {
+    #[error("{required} bytes do not fit in a buffer of {capacity} bytes")]
+    CapacityExceeded { required: u64, capacity: u64 },
+}
 
 pub trait Pipeline: Sized {
     type E;
@@ -10,17 +106,27 @@ pub trait Pipeline: Sized {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        cache: Option<&wgpu::PipelineCache>,
     ) -> Result<Self, Self::E>;
-    fn update(&self, scene: &mut Scene, device: &wgpu::Device, queue: &wgpu::Queue);
+    fn update(&mut self, scene: &mut Scene, device: &wgpu::Device, queue: &wgpu::Queue);
+    // The depth load op applies to the first pass that uses the depth target.
+    // Passes after it load the depth that pass left.
     fn draw(
         &self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
         view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
         depth_view: &wgpu::TextureView,
+        depth_load: wgpu::LoadOp<f32>,
     );
 }
 
+pub fn grown_capacity(len: usize) -> u64 {
+    len.max(1).next_power_of_two() as u64
+}
+
 pub fn create_uniform_buffer<T>(device: &wgpu::Device, count: Option<u64>) -> wgpu::Buffer {
     let label = format!("{} Buffer", std::any::type_name::<T>());
     let buffer_descriptor = wgpu::BufferDescriptor {
@@ -50,3 +156,78 @@ pub fn create_storage_buffer<T>(device: &wgpu::Device, count: Option<u64>) -> wg
 
     device.create_buffer(&buffer_descriptor)
 }
+
+pub fn upload_cache<T, U: bytemuck::Pod>(
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    cache: &mut Cache<T>,
+    data: impl FnOnce(&T) -> &U,
+) -> Result<(), UploadError> {
+    if !cache.is_dirty() {
+        return Ok(());
+    }
+
+    let bytes = bytemuck::bytes_of(data(cache));
+    if bytes.len() as u64 > buffer.size() {
+        return Err(UploadError::CapacityExceeded {
+            required: bytes.len() as u64,
+            capacity: buffer.size(),
+        });
+    }
+
+    queue.write_buffer(buffer, 0, bytes);
+    cache.clear();
+    Ok(())
+}
+
+pub fn upload_vec_cache<T: bytemuck::Pod>(
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    cache: &mut VecCache<T>,
+) -> Result<(), UploadError> {
+    if !cache.is_dirty() {
+        return Ok(());
+    }
+
+    let bytes: &[u8] = bytemuck::cast_slice(cache.values());
+    if bytes.len() as u64 > buffer.size() {
+        return Err(UploadError::CapacityExceeded {
+            required: bytes.len() as u64,
+            capacity: buffer.size(),
+        });
+    }
+
+    queue.write_buffer(buffer, 0, bytes);
+    // Zero the unused tail so removed entries don't linger for shaders that
+    // iterate the whole buffer.
+    let tail = buffer.size() - bytes.len() as u64;
+    if tail > 0 {
+        queue.write_buffer(buffer, bytes.len() as u64, &vec![0; tail as usize]);
+    }
+    cache.clear();
+    Ok(())
+}
+
+pub fn upload_vec_cache_elements<T: bytemuck::Pod>(
+    queue: &wgpu::Queue,
+    buffers: &[wgpu::Buffer],
+    cache: &mut VecCache<T>,
+) -> Result<(), UploadError> {
+    if !cache.is_dirty() {
+        return Ok(());
+    }
+
+    let element_size = std::mem::size_of::<T>() as u64;
+    if cache.len() > buffers.len() {
+        return Err(UploadError::CapacityExceeded {
+            required: element_size * cache.len() as u64,
+            capacity: element_size * buffers.len() as u64,
+        });
+    }
+
+    for (value, buffer) in cache.values().iter().zip(buffers) {
+        queue.write_buffer(buffer, 0, bytemuck::bytes_of(value));
+    }
+    cache.clear();
+    Ok(())
+}
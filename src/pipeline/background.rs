@@ -0,0 +1,158 @@
+use super::{
+    Viewport, clear_color, create_uniform_buffer, encode_color, set_viewport, viewport_load_op,
+};
+
+// Linear colors at the top and bottom edges of each viewport
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gradient {
+    pub top: [f32; 3],
+    pub bottom: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Colors {
+    top: [f32; 4],
+    bottom: [f32; 4],
+}
+
+// Clears the target and fills it with a vertical gradient before the scene is
+// drawn, so the passes after it load the color instead of clearing it.
+pub struct Background {
+    pipeline: wgpu::RenderPipeline,
+    group: wgpu::BindGroup,
+    format: wgpu::TextureFormat,
+}
+
+impl Background {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        gradient: Gradient,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Background {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/background.wgsl"));
+        let color_state_target = [Some(wgpu::ColorTargetState {
+            format: config.format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::all(),
+        })];
+
+        let group_layout_descriptor = wgpu::BindGroupLayoutDescriptor {
+            label: Some("Background Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        };
+        let group_layout = device.create_bind_group_layout(&group_layout_descriptor);
+
+        let encode = |[r, g, b]: [f32; 3]| {
+            let color = wgpu::Color {
+                r: r as f64,
+                g: g as f64,
+                b: b as f64,
+                a: 1.0,
+            };
+            let color = encode_color(color, config.format);
+            [color.r as f32, color.g as f32, color.b as f32, 1.0]
+        };
+        let colors = Colors {
+            top: encode(gradient.top),
+            bottom: encode(gradient.bottom),
+        };
+        let colors_buffer = create_uniform_buffer::<Colors>(device, None);
+        queue.write_buffer(&colors_buffer, 0, bytemuck::bytes_of(&colors));
+
+        let group_descriptor = wgpu::BindGroupDescriptor {
+            label: Some("Background Bind Group"),
+            layout: &group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(colors_buffer.as_entire_buffer_binding()),
+            }],
+        };
+        let group = device.create_bind_group(&group_descriptor);
+
+        let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
+            label: Some("Background Pipeline Layout"),
+            bind_group_layouts: &[&group_layout],
+            push_constant_ranges: &[],
+        };
+        let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_descriptor);
+
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            ..Default::default()
+        };
+        let pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+            label: Some("Background Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vert_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("frag_main"),
+                compilation_options: compilation_options.clone(),
+                targets: &color_state_target,
+            }),
+            multiview: None,
+            cache,
+        };
+        let pipeline = device.create_render_pipeline(&pipeline_descriptor);
+
+        Background {
+            pipeline,
+            group,
+            format: config.format,
+        }
+    }
+
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        viewport: Option<Viewport>,
+    ) {
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Background Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: viewport_load_op(viewport, clear_color(self.format)),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
+        set_viewport(&mut render_pass, viewport);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,255 @@
+use super::{Viewport, create_storage_buffer, create_uniform_buffer, grown_capacity, set_viewport};
+use crate::scene::Scene;
+
+use cgmath::EuclideanSpace;
+
+const VISIBLE_COLOR: [f32; 4] = [0.2, 1.0, 0.2, 1.0];
+const CULLED_COLOR: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BoundsCamera {
+    pub view_proj: [[f32; 4]; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BoundsBox {
+    pub model: [[f32; 4]; 4],
+    pub color: [f32; 4],
+}
+
+pub struct BoundsOverlay {
+    pipeline: wgpu::RenderPipeline,
+    group_layout: wgpu::BindGroupLayout,
+    group: wgpu::BindGroup,
+    camera_buffer: wgpu::Buffer,
+    boxes_buffer: wgpu::Buffer,
+    boxes_capacity: u64,
+    box_count: u32,
+}
+
+impl BoundsOverlay {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> BoundsOverlay {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/bounds.wgsl"));
+        let color_state_target = [Some(wgpu::ColorTargetState {
+            format: config.format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::all(),
+        })];
+
+        let group_layout_descriptor = wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bounds Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        };
+        let group_layout = device.create_bind_group_layout(&group_layout_descriptor);
+
+        let camera_buffer = create_uniform_buffer::<BoundsCamera>(device, None);
+        let boxes_capacity = grown_capacity(0);
+        let boxes_buffer = create_storage_buffer::<BoundsBox>(device, Some(boxes_capacity));
+        let group = Self::create_group(device, &group_layout, &camera_buffer, &boxes_buffer);
+
+        let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
+            label: Some("Bounds Pipeline Layout"),
+            bind_group_layouts: &[&group_layout],
+            push_constant_ranges: &[],
+        };
+        let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_descriptor);
+
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            ..Default::default()
+        };
+        let pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+            label: Some("Bounds Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vert_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("frag_main"),
+                compilation_options: compilation_options.clone(),
+                targets: &color_state_target,
+            }),
+            multiview: None,
+            cache,
+        };
+        let pipeline = device.create_render_pipeline(&pipeline_descriptor);
+
+        BoundsOverlay {
+            pipeline,
+            group_layout,
+            group,
+            camera_buffer,
+            boxes_buffer,
+            boxes_capacity,
+            box_count: 0,
+        }
+    }
+
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) {
+        let view_proj = cgmath::Matrix4::from(scene.camera.mesh_camera.view_proj);
+        let boxes: Vec<BoundsBox> = scene
+            .objects
+            .values()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, object)| {
+                let bounds = scene.model_bounds.get(scene.object_model(i))?;
+                let model = cgmath::Matrix4::from(object.model)
+                    * cgmath::Matrix4::from_translation(bounds.min.to_vec())
+                    * cgmath::Matrix4::from_nonuniform_scale(
+                        bounds.max.x - bounds.min.x,
+                        bounds.max.y - bounds.min.y,
+                        bounds.max.z - bounds.min.z,
+                    );
+                let visible = scene.is_visible(i) && Self::in_frustum(view_proj * model);
+                Some(BoundsBox {
+                    model: model.into(),
+                    color: if visible { VISIBLE_COLOR } else { CULLED_COLOR },
+                })
+            })
+            .collect();
+
+        if boxes.len() as u64 > self.boxes_capacity {
+            self.boxes_capacity = grown_capacity(boxes.len());
+            self.boxes_buffer =
+                create_storage_buffer::<BoundsBox>(device, Some(self.boxes_capacity));
+            self.group = Self::create_group(
+                device,
+                &self.group_layout,
+                &self.camera_buffer,
+                &self.boxes_buffer,
+            );
+        }
+
+        let camera = BoundsCamera {
+            view_proj: view_proj.into(),
+        };
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&camera));
+        queue.write_buffer(&self.boxes_buffer, 0, bytemuck::cast_slice(&boxes));
+        self.box_count = boxes.len() as u32;
+    }
+
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        viewport: Option<Viewport>,
+    ) {
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Bounds Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
+        set_viewport(&mut render_pass, viewport);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.group, &[]);
+        render_pass.draw(0..24, 0..self.box_count);
+    }
+
+    // A box is culled when all of its corners lie outside the same clip plane
+    fn in_frustum(clip_from_box: cgmath::Matrix4<f32>) -> bool {
+        let corners = (0..8).map(|corner| {
+            let position = cgmath::Point3::new(
+                (corner & 1) as f32,
+                ((corner >> 1) & 1) as f32,
+                ((corner >> 2) & 1) as f32,
+            );
+            clip_from_box * position.to_homogeneous()
+        });
+
+        let mut outside = [true; 6];
+        for clip in corners {
+            let planes = [
+                clip.x < -clip.w,
+                clip.x > clip.w,
+                clip.y < -clip.w,
+                clip.y > clip.w,
+                clip.z < 0.0,
+                clip.z > clip.w,
+            ];
+            for (outside, plane) in outside.iter_mut().zip(planes) {
+                *outside &= plane;
+            }
+        }
+        !outside.contains(&true)
+    }
+
+    fn create_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        camera_buffer: &wgpu::Buffer,
+        boxes_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        let group_descriptor = wgpu::BindGroupDescriptor {
+            label: Some("Bounds Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(
+                        camera_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(
+                        boxes_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+            ],
+        };
+
+        device.create_bind_group(&group_descriptor)
+    }
+}
@@ -0,0 +1,236 @@
+use super::{create_storage_buffer, create_uniform_buffer};
+use crate::scene::Camera;
+
+use cgmath::SquareMatrix;
+
+pub const TILE_SIZE: u32 = 16;
+pub const MAX_TILE_LIGHTS: u32 = 63;
+
+const WORKGROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Tiles {
+    pub inv_view_proj: [[f32; 4]; 4],
+    pub eye: [f32; 3],
+    pub light_count: u32,
+    pub screen_size: [f32; 2],
+    pub tile_count: [u32; 2],
+    pub origin: [f32; 2],
+    pub _padding: [f32; 2],
+}
+
+pub struct LightCulling {
+    pipeline: wgpu::ComputePipeline,
+    group_layout: wgpu::BindGroupLayout,
+    group: wgpu::BindGroup,
+    tiles_buffer: wgpu::Buffer,
+    tile_lights_buffer: wgpu::Buffer,
+    screen_size: [f32; 2],
+    tile_count: [u32; 2],
+    origin: [f32; 2],
+}
+
+impl LightCulling {
+    pub fn new(
+        device: &wgpu::Device,
+        point_lights_buffer: &wgpu::Buffer,
+        width: u32,
+        height: u32,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> LightCulling {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/cull.wgsl"));
+
+        let group_layout_descriptor = wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light Culling Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        };
+        let group_layout = device.create_bind_group_layout(&group_layout_descriptor);
+
+        let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
+            label: Some("Light Culling Pipeline Layout"),
+            bind_group_layouts: &[&group_layout],
+            push_constant_ranges: &[],
+        };
+        let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_descriptor);
+
+        let pipeline_descriptor = wgpu::ComputePipelineDescriptor {
+            label: Some("Light Culling Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("cull_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache,
+        };
+        let pipeline = device.create_compute_pipeline(&pipeline_descriptor);
+
+        let (screen_size, tile_count) = Self::tile_grid(width, height);
+        let tiles_buffer = create_uniform_buffer::<Tiles>(device, None);
+        let tile_lights_buffer = Self::create_tile_lights_buffer(device, tile_count);
+        let group = Self::create_group(
+            device,
+            &group_layout,
+            &tiles_buffer,
+            point_lights_buffer,
+            &tile_lights_buffer,
+        );
+
+        LightCulling {
+            pipeline,
+            group_layout,
+            group,
+            tiles_buffer,
+            tile_lights_buffer,
+            screen_size,
+            tile_count,
+            origin: [0.0; 2],
+        }
+    }
+
+    pub fn tiles_buffer(&self) -> &wgpu::Buffer {
+        &self.tiles_buffer
+    }
+
+    pub fn tile_lights_buffer(&self) -> &wgpu::Buffer {
+        &self.tile_lights_buffer
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        point_lights_buffer: &wgpu::Buffer,
+        width: u32,
+        height: u32,
+    ) {
+        (self.screen_size, self.tile_count) = Self::tile_grid(width, height);
+        self.tile_lights_buffer = Self::create_tile_lights_buffer(device, self.tile_count);
+        self.set_point_lights(device, point_lights_buffer);
+    }
+
+    // Offset of the culled region within the render target, in pixels
+    pub fn set_origin(&mut self, x: u32, y: u32) {
+        self.origin = [x as f32, y as f32];
+    }
+
+    pub fn set_point_lights(&mut self, device: &wgpu::Device, point_lights_buffer: &wgpu::Buffer) {
+        self.group = Self::create_group(
+            device,
+            &self.group_layout,
+            &self.tiles_buffer,
+            point_lights_buffer,
+            &self.tile_lights_buffer,
+        );
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera, light_count: usize) {
+        let view_proj = cgmath::Matrix4::from(camera.mesh_camera.view_proj);
+        let tiles = Tiles {
+            inv_view_proj: view_proj
+                .invert()
+                .unwrap_or(cgmath::Matrix4::identity())
+                .into(),
+            eye: camera.view_position().into(),
+            light_count: light_count as u32,
+            screen_size: self.screen_size,
+            tile_count: self.tile_count,
+            origin: self.origin,
+            _padding: [0.0; 2],
+        };
+        queue.write_buffer(&self.tiles_buffer, 0, bytemuck::bytes_of(&tiles));
+    }
+
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
+        let compute_pass_descriptor = wgpu::ComputePassDescriptor {
+            label: Some("Light Culling Compute Pass"),
+            timestamp_writes: None,
+        };
+
+        let mut compute_pass = encoder.begin_compute_pass(&compute_pass_descriptor);
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.group, &[]);
+        compute_pass.dispatch_workgroups(
+            self.tile_count[0].div_ceil(WORKGROUP_SIZE),
+            self.tile_count[1].div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+
+    fn tile_grid(width: u32, height: u32) -> ([f32; 2], [u32; 2]) {
+        let (width, height) = (width.max(1), height.max(1));
+        (
+            [width as f32, height as f32],
+            [width.div_ceil(TILE_SIZE), height.div_ceil(TILE_SIZE)],
+        )
+    }
+
+    fn create_tile_lights_buffer(device: &wgpu::Device, tile_count: [u32; 2]) -> wgpu::Buffer {
+        let tiles = tile_count[0] as u64 * tile_count[1] as u64;
+        create_storage_buffer::<u32>(device, Some(tiles * (MAX_TILE_LIGHTS as u64 + 1)))
+    }
+
+    fn create_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        tiles_buffer: &wgpu::Buffer,
+        point_lights_buffer: &wgpu::Buffer,
+        tile_lights_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        let group_descriptor = wgpu::BindGroupDescriptor {
+            label: Some("Light Culling Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(
+                        tiles_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(
+                        point_lights_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(
+                        tile_lights_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+            ],
+        };
+
+        device.create_bind_group(&group_descriptor)
+    }
+}
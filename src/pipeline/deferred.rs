@@ -0,0 +1,376 @@
+use super::mesh::{Vertex, create_shader};
+use super::{Viewport, oriented_depth_bias, set_viewport};
+
+const POSITION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// Emissive and albedo are kept at 8 bits so the targets fit in the 32 bytes per
+// sample every adapter supports
+const EMISSIVE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Shading {
+    #[default]
+    Forward,
+    Deferred,
+}
+
+// Settings shared with the forward mesh pipelines
+#[derive(Clone, Copy, Debug)]
+pub struct DeferredOptions<'a> {
+    pub sample_count: u32,
+    pub reverse_z: bool,
+    pub depth_bias: wgpu::DepthBiasState,
+    pub cache: Option<&'a wgpu::PipelineCache>,
+}
+
+struct GBuffer {
+    position: wgpu::TextureView,
+    normal: wgpu::TextureView,
+    emissive: wgpu::TextureView,
+    albedo: wgpu::TextureView,
+    depth: wgpu::TextureView,
+}
+
+pub struct Deferred {
+    geometry_pipeline: wgpu::RenderPipeline,
+    flipped_geometry_pipeline: wgpu::RenderPipeline,
+    lighting_pipeline: wgpu::RenderPipeline,
+    group_layout: wgpu::BindGroupLayout,
+    group: wgpu::BindGroup,
+    gbuffer: GBuffer,
+    camera_buffer: wgpu::Buffer,
+    reverse_z: bool,
+}
+
+impl Deferred {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        geometry_pipeline_layout: &wgpu::PipelineLayout,
+        storage_group_layout: &wgpu::BindGroupLayout,
+        camera_buffer: &wgpu::Buffer,
+        options: DeferredOptions,
+    ) -> Deferred {
+        let DeferredOptions {
+            sample_count,
+            reverse_z,
+            depth_bias,
+            cache,
+        } = options;
+        let geometry_shader = create_shader(device);
+        let lighting_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Deferred Lighting Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("../shaders/lighting.wgsl"),
+                    include_str!("../shaders/deferred.wgsl")
+                )
+                .into(),
+            ),
+        });
+
+        let group_layout_descriptor = wgpu::BindGroupLayoutDescriptor {
+            label: Some("Deferred Lighting Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                gbuffer_layout_entry(1),
+                gbuffer_layout_entry(2),
+                gbuffer_layout_entry(3),
+                gbuffer_layout_entry(4),
+            ],
+        };
+        let group_layout = device.create_bind_group_layout(&group_layout_descriptor);
+
+        let lighting_pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
+            label: Some("Deferred Lighting Pipeline Layout"),
+            bind_group_layouts: &[&group_layout, storage_group_layout],
+            push_constant_ranges: &[],
+        };
+        let lighting_pipeline_layout =
+            device.create_pipeline_layout(&lighting_pipeline_layout_descriptor);
+
+        let gbuffer_targets = [
+            POSITION_FORMAT,
+            NORMAL_FORMAT,
+            EMISSIVE_FORMAT,
+            ALBEDO_FORMAT,
+        ]
+        .map(|format| {
+            Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::all(),
+            })
+        });
+        let color_state_target = [Some(wgpu::ColorTargetState {
+            format: config.format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::all(),
+        })];
+
+        let depth_stencil_state = wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_compare: if reverse_z {
+                wgpu::CompareFunction::GreaterEqual
+            } else {
+                wgpu::CompareFunction::LessEqual
+            },
+            depth_write_enabled: true,
+            stencil: wgpu::StencilState::default(),
+            bias: oriented_depth_bias(depth_bias, reverse_z),
+        };
+
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            ..Default::default()
+        };
+        let mut geometry_pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+            label: Some("G-Buffer Pipeline"),
+            layout: Some(geometry_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &geometry_shader,
+                entry_point: Some("vert_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[Vertex::LAYOUT],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(depth_stencil_state),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &geometry_shader,
+                entry_point: Some("gbuffer_main"),
+                compilation_options: compilation_options.clone(),
+                targets: &gbuffer_targets,
+            }),
+            multiview: None,
+            cache,
+        };
+        let geometry_pipeline = device.create_render_pipeline(&geometry_pipeline_descriptor);
+        geometry_pipeline_descriptor.label = Some("Flipped G-Buffer Pipeline");
+        geometry_pipeline_descriptor.primitive.front_face = wgpu::FrontFace::Cw;
+        let flipped_geometry_pipeline =
+            device.create_render_pipeline(&geometry_pipeline_descriptor);
+
+        let lighting_pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+            label: Some("Deferred Lighting Pipeline"),
+            layout: Some(&lighting_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &lighting_shader,
+                entry_point: Some("vert_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &lighting_shader,
+                entry_point: Some("frag_main"),
+                compilation_options: compilation_options.clone(),
+                targets: &color_state_target,
+            }),
+            multiview: None,
+            cache,
+        };
+        let lighting_pipeline = device.create_render_pipeline(&lighting_pipeline_descriptor);
+
+        let gbuffer = create_gbuffer(device, config.width, config.height);
+        let group = create_group(device, &group_layout, camera_buffer, &gbuffer);
+
+        Deferred {
+            geometry_pipeline,
+            flipped_geometry_pipeline,
+            lighting_pipeline,
+            group_layout,
+            group,
+            gbuffer,
+            camera_buffer: camera_buffer.clone(),
+            reverse_z,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.gbuffer = create_gbuffer(device, width, height);
+        self.group = create_group(
+            device,
+            &self.group_layout,
+            &self.camera_buffer,
+            &self.gbuffer,
+        );
+    }
+
+    pub fn begin_geometry_pass<'a>(
+        &self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        front_face: wgpu::FrontFace,
+        viewport: Option<Viewport>,
+    ) -> wgpu::RenderPass<'a> {
+        let gbuffer_attachment = |view| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        };
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("G-Buffer Render Pass"),
+            color_attachments: &[
+                gbuffer_attachment(&self.gbuffer.position),
+                gbuffer_attachment(&self.gbuffer.normal),
+                gbuffer_attachment(&self.gbuffer.emissive),
+                gbuffer_attachment(&self.gbuffer.albedo),
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.gbuffer.depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(if self.reverse_z { 0.0 } else { 1.0 }),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            ..Default::default()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
+        set_viewport(&mut render_pass, viewport);
+        render_pass.set_pipeline(match front_face {
+            wgpu::FrontFace::Ccw => &self.geometry_pipeline,
+            wgpu::FrontFace::Cw => &self.flipped_geometry_pipeline,
+        });
+        render_pass
+    }
+
+    pub fn draw_lighting(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        storage_group: &wgpu::BindGroup,
+        viewport: Option<Viewport>,
+        color_load: wgpu::LoadOp<wgpu::Color>,
+    ) {
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Deferred Lighting Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: color_load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
+        set_viewport(&mut render_pass, viewport);
+        render_pass.set_pipeline(&self.lighting_pipeline);
+        render_pass.set_bind_group(0, &self.group, &[]);
+        render_pass.set_bind_group(1, storage_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn gbuffer_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn create_gbuffer(device: &wgpu::Device, width: u32, height: u32) -> GBuffer {
+    let create_view = |label, format| {
+        let texture_descriptor = wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                ..Default::default()
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        };
+        device
+            .create_texture(&texture_descriptor)
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    };
+
+    GBuffer {
+        position: create_view("G-Buffer Position Texture", POSITION_FORMAT),
+        normal: create_view("G-Buffer Normal Texture", NORMAL_FORMAT),
+        emissive: create_view("G-Buffer Emissive Texture", EMISSIVE_FORMAT),
+        albedo: create_view("G-Buffer Albedo Texture", ALBEDO_FORMAT),
+        depth: create_view("G-Buffer Depth Texture", wgpu::TextureFormat::Depth32Float),
+    }
+}
+
+fn create_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    camera_buffer: &wgpu::Buffer,
+    gbuffer: &GBuffer,
+) -> wgpu::BindGroup {
+    let group_descriptor = wgpu::BindGroupDescriptor {
+        label: Some("Deferred Lighting Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(camera_buffer.as_entire_buffer_binding()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&gbuffer.position),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&gbuffer.normal),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&gbuffer.emissive),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&gbuffer.albedo),
+            },
+        ],
+    };
+
+    device.create_bind_group(&group_descriptor)
+}
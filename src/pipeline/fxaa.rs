@@ -0,0 +1,155 @@
+// Antialiases the finished frame. There is no tone mapping pass, so the input is
+// the sRGB target the scene was drawn into, already clamped to 0..1. Samples
+// come back linear and are re-encoded to sRGB to find edges perceptually.
+pub struct Fxaa {
+    pipeline: wgpu::RenderPipeline,
+    group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    group: wgpu::BindGroup,
+}
+
+impl Fxaa {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        input: &wgpu::TextureView,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Fxaa {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/fxaa.wgsl"));
+        let color_state_target = [Some(wgpu::ColorTargetState {
+            format: config.format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::all(),
+        })];
+
+        let group_layout_descriptor = wgpu::BindGroupLayoutDescriptor {
+            label: Some("FXAA Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        };
+        let group_layout = device.create_bind_group_layout(&group_layout_descriptor);
+
+        let sampler_descriptor = wgpu::SamplerDescriptor {
+            label: Some("FXAA Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        };
+        let sampler = device.create_sampler(&sampler_descriptor);
+        let group = Self::create_group(device, &group_layout, &sampler, input);
+
+        let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
+            label: Some("FXAA Pipeline Layout"),
+            bind_group_layouts: &[&group_layout],
+            push_constant_ranges: &[],
+        };
+        let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_descriptor);
+
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            ..Default::default()
+        };
+        let pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+            label: Some("FXAA Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vert_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("frag_main"),
+                compilation_options: compilation_options.clone(),
+                targets: &color_state_target,
+            }),
+            multiview: None,
+            cache,
+        };
+        let pipeline = device.create_render_pipeline(&pipeline_descriptor);
+
+        Fxaa {
+            pipeline,
+            group_layout,
+            sampler,
+            group,
+        }
+    }
+
+    fn create_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        input: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        let group_descriptor = wgpu::BindGroupDescriptor {
+            label: Some("FXAA Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        };
+
+        device.create_bind_group(&group_descriptor)
+    }
+
+    pub fn set_input(&mut self, device: &wgpu::Device, input: &wgpu::TextureView) {
+        self.group = Self::create_group(device, &self.group_layout, &self.sampler, input);
+    }
+
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("FXAA Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
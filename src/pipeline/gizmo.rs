@@ -0,0 +1,178 @@
+use super::create_uniform_buffer;
+use crate::scene::Camera;
+
+pub const GIZMO_SIZE: u32 = 96;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GizmoCorner {
+    TopLeft,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GizmoOptions {
+    pub corner: GizmoCorner,
+    pub size: u32,
+}
+
+impl Default for GizmoOptions {
+    fn default() -> Self {
+        GizmoOptions {
+            corner: GizmoCorner::default(),
+            size: GIZMO_SIZE,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GizmoUniform {
+    pub rotation: [[f32; 4]; 4],
+}
+
+pub struct Gizmo {
+    pipeline: wgpu::RenderPipeline,
+    buffer: wgpu::Buffer,
+    group: wgpu::BindGroup,
+    options: GizmoOptions,
+}
+
+impl Gizmo {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        options: GizmoOptions,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Gizmo {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/gizmo.wgsl"));
+        let color_state_target = [Some(wgpu::ColorTargetState {
+            format: config.format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::all(),
+        })];
+
+        let group_layout_descriptor = wgpu::BindGroupLayoutDescriptor {
+            label: Some("Gizmo Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        };
+        let group_layout = device.create_bind_group_layout(&group_layout_descriptor);
+
+        let buffer = create_uniform_buffer::<GizmoUniform>(device, None);
+        let group_descriptor = wgpu::BindGroupDescriptor {
+            label: Some("Gizmo Bind Group"),
+            layout: &group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(buffer.as_entire_buffer_binding()),
+            }],
+        };
+        let group = device.create_bind_group(&group_descriptor);
+
+        let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
+            label: Some("Gizmo Pipeline Layout"),
+            bind_group_layouts: &[&group_layout],
+            push_constant_ranges: &[],
+        };
+        let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_descriptor);
+
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            ..Default::default()
+        };
+        let pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+            label: Some("Gizmo Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vert_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("frag_main"),
+                compilation_options: compilation_options.clone(),
+                targets: &color_state_target,
+            }),
+            multiview: None,
+            cache,
+        };
+        let pipeline = device.create_render_pipeline(&pipeline_descriptor);
+
+        Gizmo {
+            pipeline,
+            buffer,
+            group,
+            options,
+        }
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let mut rotation = camera.view;
+        rotation.w = cgmath::Vector4::unit_w();
+        let uniform = GizmoUniform {
+            rotation: rotation.into(),
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        (width, height): (u32, u32),
+    ) {
+        let size = self.options.size.min(width).min(height);
+        if size == 0 {
+            return;
+        }
+        let (x, y) = match self.options.corner {
+            GizmoCorner::TopLeft => (0, 0),
+            GizmoCorner::TopRight => (width - size, 0),
+            GizmoCorner::BottomLeft => (0, height - size),
+            GizmoCorner::BottomRight => (width - size, height - size),
+        };
+
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Gizmo Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
+        render_pass.set_viewport(x as f32, y as f32, size as f32, size as f32, 0.0, 1.0);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
@@ -1,7 +1,46 @@
-use super::{Pipeline, create_storage_buffer, create_uniform_buffer};
-use std::iter::zip;
+use super::{
+    Pipeline, UploadError, Viewport, clear_color, create_storage_buffer, create_uniform_buffer,
+    grown_capacity, oriented_depth_bias, set_viewport, upload_cache, upload_vec_cache,
+    upload_vec_cache_elements, viewport_load_op,
+};
+use super::background::{Background, Gradient};
+use super::culling::LightCulling;
+use super::deferred::{Deferred, DeferredOptions, Shading};
+use super::normals::{NormalLines, NormalSource};
+use super::points::{PointCloud, PointSprites};
+use super::shadow::{Casters, Shadows};
+use log::{error, warn};
+use thiserror::Error;
+use wgpu::util::RenderEncoder;
 
-use crate::scene::{InitData, Scene};
+use crate::scene::{DebugMode, InitData, MaterialGroup, Model, Scene};
+
+pub const POINT_LIGHT_CUTOFF: f32 = 0.01;
+// Default limit on each kind of light. The device's storage buffer binding size
+// can lower it further.
+pub const MAX_LIGHTS: usize = 4096;
+// Depth bias of the scene's surfaces, pushing them away from the camera. None by
+// default; a constant of 1 to 4 with a slope scale of 1.0 to 2.0 is usually
+// enough to let coplanar decals and overlays drawn without a bias win.
+pub const DEPTH_BIAS: wgpu::DepthBiasState = wgpu::DepthBiasState {
+    constant: 0,
+    slope_scale: 0.0,
+    clamp: 0.0,
+};
+// The highest anisotropy wgpu accepts for a sampler
+pub const MAX_ANISOTROPY: u16 = 16;
+const WIREFRAME_DEPTH_BIAS: i32 = 2;
+const WIREFRAME_SLOPE_BIAS: f32 = 1.0;
+
+#[derive(Error, Debug)]
+pub enum MeshError {
+    #[error("{count} {kind} lights exceed the limit of {max}")]
+    TooManyLights {
+        kind: &'static str,
+        count: usize,
+        max: usize,
+    },
+}
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -9,15 +48,22 @@ pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
     pub uv: [f32; 2],
+    pub color: [f32; 3],
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct PointLight {
     pub position: [f32; 3],
-    pub _padding0: f32,
+    pub radius: f32,
     pub color: [f32; 3],
     pub strength: f32,
+    pub casts_shadow: u32,
+    pub shadow_index: i32,
+    pub shadow_near: f32,
+    pub shadow_far: f32,
+    pub enabled: u32,
+    pub _padding: [u32; 3],
 }
 
 #[repr(C)]
@@ -29,22 +75,154 @@ pub struct DirectionalLight {
     pub _padding1: f32,
     pub color: [f32; 3],
     pub strength: f32,
+    pub casts_shadow: u32,
+    pub shadow_index: i32,
+    pub enabled: u32,
+    pub _padding2: u32,
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Object {
     pub model: [[f32; 4]; 4],
+    pub emissive: [f32; 3],
     pub metallic: f32,
+    pub alpha_cutoff: f32,
     pub _padding: [f32; 3],
 }
 
+// These mirror structs in lighting.wgsl and mesh.wgsl, where vec3 fields are
+// aligned to 16 bytes and struct sizes round up to 16
+const _: () = {
+    assert!(size_of::<PointLight>() == 64);
+    assert!(std::mem::offset_of!(PointLight, color) == 16);
+    assert!(std::mem::offset_of!(PointLight, casts_shadow) == 32);
+    assert!(std::mem::offset_of!(PointLight, enabled) == 48);
+    assert!(size_of::<DirectionalLight>() == 64);
+    assert!(std::mem::offset_of!(DirectionalLight, direction) == 16);
+    assert!(std::mem::offset_of!(DirectionalLight, color) == 32);
+    assert!(std::mem::offset_of!(DirectionalLight, casts_shadow) == 48);
+    assert!(std::mem::offset_of!(DirectionalLight, enabled) == 56);
+    assert!(size_of::<Object>() == 96);
+    assert!(std::mem::offset_of!(Object, emissive) == 64);
+    assert!(std::mem::offset_of!(Object, alpha_cutoff) == 80);
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Material {
+    pub color: [f32; 3],
+    pub metallic: f32,
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Camera {
     pub position: [f32; 3],
-    pub _padding: f32,
+    pub far: f32,
     pub view_proj: [[f32; 4]; 4],
+    pub near: f32,
+    pub _padding: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Globals {
+    pub debug_mode: u32,
+    pub _padding: [u32; 3],
+    pub selection_color: [f32; 4],
+    pub wireframe_color: [f32; 4],
+    pub ambient_color: [f32; 3],
+    pub ambient_intensity: f32,
+}
+
+// Objects are drawn a layer at a time, in this order
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layer {
+    Background = 0,
+    #[default]
+    World = 1,
+    Overlay = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayerState {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub blend: wgpu::BlendState,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 3] = [Layer::Background, Layer::World, Layer::Overlay];
+
+    pub fn next(self) -> Layer {
+        Layer::ALL[(self as usize + 1) % Layer::ALL.len()]
+    }
+
+    // Background objects never hide the world and overlay objects are never
+    // hidden by it
+    pub fn default_state(self) -> LayerState {
+        LayerState {
+            depth_test: self == Layer::World,
+            depth_write: self == Layer::World,
+            blend: wgpu::BlendState::REPLACE,
+        }
+    }
+}
+
+impl Object {
+    pub fn from_transform(model: cgmath::Matrix4<f32>, metallic: f32) -> Object {
+        Object {
+            model: model.into(),
+            emissive: [0.0, 0.0, 0.0],
+            metallic,
+            alpha_cutoff: 0.0,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+impl Material {
+    // A negative metallic defers to the object's own value
+    pub const DEFAULT: Material = Material {
+        color: [1.0, 1.0, 1.0],
+        metallic: -1.0,
+    };
+
+    // MTL has no metallic term of its own, so only the PBR extension's Pm is read
+    pub fn from_tobj(material: &tobj::Material) -> Material {
+        Material {
+            color: material.diffuse.unwrap_or(Material::DEFAULT.color),
+            metallic: material
+                .unknown_param
+                .get("Pm")
+                .and_then(|metallic| metallic.parse().ok())
+                .unwrap_or(Material::DEFAULT.metallic),
+        }
+    }
+}
+
+impl Camera {
+    pub fn from_view_proj(
+        position: cgmath::Point3<f32>,
+        view_proj: cgmath::Matrix4<f32>,
+        near: f32,
+        far: f32,
+    ) -> Camera {
+        Camera {
+            position: position.into(),
+            far,
+            view_proj: view_proj.into(),
+            near,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+impl PointLight {
+    pub fn radius_for_strength(strength: f32) -> f32 {
+        (strength / POINT_LIGHT_CUTOFF - 1.0).max(0.0).sqrt()
+    }
 }
 
 impl Vertex {
@@ -55,25 +233,78 @@ impl Vertex {
             0 => Float32x3,
             1 => Float32x3,
             2 => Float32x2,
+            3 => Float32x3,
         ],
     };
 }
 
+// Shared by every material bind group. Materials without a texture sample a
+// single white texel. The sampler is bound with the storage group instead.
+struct MaterialBindings {
+    layout: wgpu::BindGroupLayout,
+    default_texture: wgpu::TextureView,
+}
+
+struct Lod {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_format: wgpu::IndexFormat,
+    material_groups: Vec<(std::ops::Range<u32>, wgpu::BindGroup)>,
+}
+
 pub struct Mesh {
-    pipeline: wgpu::RenderPipeline,
+    pipelines: Vec<(wgpu::RenderPipeline, wgpu::RenderPipeline)>,
+    overlay_pipeline: Option<wgpu::RenderPipeline>,
+    wireframe_pipeline: Option<wgpu::RenderPipeline>,
+    uniform_group_layout: wgpu::BindGroupLayout,
+    storage_group_layout: wgpu::BindGroupLayout,
     uniform_groups: Vec<wgpu::BindGroup>,
     storage_group: wgpu::BindGroup,
     point_lights_buffer: wgpu::Buffer,
     directional_lights_buffer: wgpu::Buffer,
     camera_buffer: wgpu::Buffer,
+    globals_buffer: wgpu::Buffer,
     object_buffers: Vec<wgpu::Buffer>,
     vertex_buffers: Vec<wgpu::Buffer>,
     index_buffers: Vec<wgpu::Buffer>,
     index_lengths: Vec<u32>,
+    index_formats: Vec<wgpu::IndexFormat>,
+    material_bindings: MaterialBindings,
+    material_sampler: wgpu::Sampler,
+    anisotropy: u16,
+    material_groups: Vec<Vec<(std::ops::Range<u32>, wgpu::BindGroup)>>,
+    lods: Vec<Vec<Lod>>,
+    lod_levels: Vec<usize>,
+    static_bundle: Option<wgpu::RenderBundle>,
+    point_sprites: PointSprites,
+    point_counts: Vec<u32>,
+    pipeline_layout: wgpu::PipelineLayout,
+    visibility: Vec<bool>,
+    layers: Vec<Layer>,
+    layer_states: [LayerState; 3],
+    object_models: Vec<usize>,
+    selected: Option<usize>,
+    show_wireframe: bool,
+    normal_lines: Option<NormalLines>,
+    show_normals: bool,
+    shadows: Shadows,
+    culling: LightCulling,
+    deferred: Option<Deferred>,
+    background: Option<Background>,
+    viewport: Option<Viewport>,
+    size: (u32, u32),
+    culling_size: (u32, u32),
+    sample_count: u32,
+    format: wgpu::TextureFormat,
+    debug_mode: u32,
+    front_face: wgpu::FrontFace,
+    reverse_z: bool,
+    depth_bias: wgpu::DepthBiasState,
+    pipeline_cache: Option<wgpu::PipelineCache>,
 }
 
 impl Pipeline for Mesh {
-    type E = ();
+    type E = MeshError;
 
     fn new(
         init_data: &InitData,
@@ -81,8 +312,11 @@ impl Pipeline for Mesh {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         config: &wgpu::SurfaceConfiguration,
-    ) -> Result<Self, ()> {
-        let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/mesh.wgsl"));
+        sample_count: u32,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Result<Self, MeshError> {
+        Self::check_light_counts(scene, device)?;
+        let shader = create_shader(device);
         let color_state_target = [Some(wgpu::ColorTargetState {
             format: config.format,
             blend: Some(wgpu::BlendState::REPLACE),
@@ -117,40 +351,20 @@ impl Pipeline for Mesh {
         let uniform_group_layout =
             device.create_bind_group_layout(&uniform_group_layout_descriptor);
 
-        let point_lights_buffer =
-            create_storage_buffer::<PointLight>(device, Some(scene.point_lights.values.len() as u64));
-        let directional_lights_buffer =
-            create_storage_buffer::<DirectionalLight>(device, Some(scene.directional_lights.values.len() as u64));
+        let shadows = Shadows::new(device, &uniform_group_layout, cache);
+        let (point_lights_buffer, directional_lights_buffer) =
+            Self::create_light_buffers(scene, device);
         let camera_buffer = create_uniform_buffer::<Camera>(device, None);
-        let mut object_buffers = Vec::new();
-        for _ in &scene.objects.values {
-            let object_buffer = create_uniform_buffer::<Object>(device, None);
-            object_buffers.push(object_buffer);
-        }
-
-        let mut uniform_groups = Vec::new();
-        for object_buffer in &object_buffers {
-            let uniform_group_descriptor = wgpu::BindGroupDescriptor {
-                label: Some("Mesh Uniform Bind Group"),
-                layout: &uniform_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Buffer(
-                            camera_buffer.as_entire_buffer_binding(),
-                        ),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Buffer(
-                            object_buffer.as_entire_buffer_binding(),
-                        ),
-                    },
-                ],
-            };
-            let uniform_group = device.create_bind_group(&uniform_group_descriptor);
-            uniform_groups.push(uniform_group);
-        }
+        let globals_buffer = create_uniform_buffer::<Globals>(device, None);
+        let (object_buffers, uniform_groups) =
+            Self::create_object_buffers(scene, device, &uniform_group_layout, &camera_buffer);
+        let culling = LightCulling::new(
+            device,
+            &point_lights_buffer,
+            config.width,
+            config.height,
+            cache,
+        );
 
         let storage_group_layout_descriptor = wgpu::BindGroupLayoutDescriptor {
             label: Some("Mesh Storage Bind Group Layout"),
@@ -172,162 +386,335 @@ impl Pipeline for Mesh {
                     min_binding_size: None,
                 },
                 count: None,
+            }, wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }, wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::CubeArray,
+                    multisampled: false,
+                },
+                count: None,
+            }, wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            }, wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
+            }, wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }, wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }, wgpu::BindGroupLayoutEntry {
+                binding: 8,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }, wgpu::BindGroupLayoutEntry {
+                binding: 9,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
             }],
         };
         let storage_group_layout =
             device.create_bind_group_layout(&storage_group_layout_descriptor);
 
-        let storage_group_descriptor = wgpu::BindGroupDescriptor {
-            label: Some("Mesh Storage Bind Group"),
-            layout: &storage_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer(point_lights_buffer.as_entire_buffer_binding()),
-            }, wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Buffer(directional_lights_buffer.as_entire_buffer_binding()),
-            }],
-        };
-        let storage_group = device.create_bind_group(&storage_group_descriptor);
+        // The material sampler is shared by every material, so it lives in the
+        // storage group where changing the anisotropy rebuilds a single group
+        let material_sampler = create_material_sampler(device, 1);
+        let storage_group = Self::create_storage_group(
+            device,
+            &storage_group_layout,
+            (&point_lights_buffer, &directional_lights_buffer),
+            &globals_buffer,
+            &shadows,
+            &culling,
+            &material_sampler,
+        );
+
+        let material_bindings = MaterialBindings::new(device, queue);
 
         let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
             label: Some("Mesh Pipeline Layout"),
-            bind_group_layouts: &[&uniform_group_layout, &storage_group_layout],
+            bind_group_layouts: &[
+                &uniform_group_layout,
+                &storage_group_layout,
+                &material_bindings.layout,
+            ],
             push_constant_ranges: &[],
         };
         let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_descriptor);
 
-        let mut vertex_buffers = Vec::new();
-        for vertex_buffer in init_data.models.iter().map(|model| &model.vertex_buffer) {
-            let buffer_descriptor = wgpu::BufferDescriptor {
-                label: Some("Vertex Buffer"),
-                size: (vertex_buffer.len() * std::mem::size_of::<Vertex>()) as u64,
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            };
-            let buffer = device.create_buffer(&buffer_descriptor);
-
-            queue.write_buffer(&buffer, 0, bytemuck::cast_slice(vertex_buffer.as_slice()));
-            queue.submit(vec![]);
-
-            vertex_buffers.push(buffer);
-        }
-
-        let mut index_buffers = Vec::new();
-        let mut index_lengths = Vec::new();
-        for index_buffer in init_data.models.iter().map(|model| &model.index_buffer) {
-            let buffer_descriptor = wgpu::BufferDescriptor {
-                label: Some("Index Buffer"),
-                size: (index_buffer.len() * std::mem::size_of::<u32>()) as u64,
-                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            };
-            let buffer = device.create_buffer(&buffer_descriptor);
-
-            queue.write_buffer(&buffer, 0, bytemuck::cast_slice(index_buffer.as_slice()));
-            queue.submit(vec![]);
-
-            index_buffers.push(buffer);
-            index_lengths.push(index_buffer.len() as u32);
-        }
-
-        let depth_stencil_state = wgpu::DepthStencilState {
-            format: wgpu::TextureFormat::Depth32Float,
-            depth_compare: wgpu::CompareFunction::LessEqual,
-            depth_write_enabled: true,
-            stencil: wgpu::StencilState {
-                ..Default::default()
-            },
-            bias: wgpu::DepthBiasState {
-                ..Default::default()
-            },
-        };
-
-        let compilation_options = wgpu::PipelineCompilationOptions {
-            ..Default::default()
-        };
-        let pipeline_descriptor = wgpu::RenderPipelineDescriptor {
-            label: Some("Mesh Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vert_main"),
-                compilation_options: compilation_options.clone(),
-                buffers: &[Vertex::LAYOUT],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(depth_stencil_state),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                ..Default::default()
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("frag_main"),
-                compilation_options: compilation_options.clone(),
-                targets: &color_state_target,
-            }),
-            multiview: None,
-            cache: None,
-        };
+        let (vertex_buffers, index_buffers, index_lengths, index_formats) =
+            Self::create_model_buffers(init_data, device, queue);
+        let material_groups = init_data
+            .models
+            .iter()
+            .map(|model| Self::create_material_groups(model, device, queue, &material_bindings))
+            .collect();
+        let lods = init_data
+            .models
+            .iter()
+            .map(|model| Self::create_lods(model, device, queue, &material_bindings))
+            .collect();
+        let point_sprites = PointSprites::new(
+            device,
+            config,
+            sample_count,
+            &uniform_group_layout,
+            scene.camera.reverse_z,
+            cache,
+        );
 
-        let pipeline = device.create_render_pipeline(&pipeline_descriptor);
+        let overlay_pipeline = Self::create_overlay_pipeline(
+            device,
+            &shader,
+            &pipeline_layout,
+            &color_state_target,
+            sample_count,
+            cache,
+        );
+        let wireframe_pipeline = Self::create_wireframe_pipeline(
+            device,
+            &shader,
+            &pipeline_layout,
+            &color_state_target,
+            sample_count,
+            scene.camera.reverse_z,
+            cache,
+        );
 
-        return Ok(Mesh {
-            pipeline: pipeline,
-            camera_buffer: camera_buffer,
-            object_buffers: object_buffers,
+        let mut mesh = Mesh {
+            pipelines: Vec::new(),
+            overlay_pipeline,
+            wireframe_pipeline,
+            uniform_group_layout,
+            storage_group_layout,
+            camera_buffer,
+            globals_buffer,
+            object_buffers,
             point_lights_buffer,
             directional_lights_buffer,
-            uniform_groups: uniform_groups,
-            storage_group: storage_group,
-            vertex_buffers: vertex_buffers,
-            index_buffers: index_buffers,
-            index_lengths: index_lengths,
-        });
+            uniform_groups,
+            storage_group,
+            vertex_buffers,
+            index_buffers,
+            index_lengths,
+            index_formats,
+            material_bindings,
+            material_sampler,
+            anisotropy: 1,
+            material_groups,
+            lods,
+            lod_levels: Vec::new(),
+            static_bundle: None,
+            point_sprites,
+            point_counts: init_data.models.iter().map(Self::point_count).collect(),
+            pipeline_layout,
+            visibility: scene.visibility.clone(),
+            layers: scene.layers.clone(),
+            layer_states: Layer::ALL.map(Layer::default_state),
+            object_models: Self::object_models(scene),
+            selected: scene.selected,
+            show_wireframe: scene.show_wireframe,
+            normal_lines: None,
+            show_normals: scene.show_normals,
+            shadows,
+            culling,
+            deferred: None,
+            background: None,
+            viewport: None,
+            size: (config.width, config.height),
+            culling_size: (config.width, config.height),
+            sample_count,
+            format: config.format,
+            debug_mode: scene.globals.debug_mode,
+            front_face: scene.front_face,
+            reverse_z: scene.camera.reverse_z,
+            depth_bias: DEPTH_BIAS,
+            pipeline_cache: cache.cloned(),
+        };
+        mesh.pipelines = Layer::ALL
+            .iter()
+            .map(|layer| mesh.create_layer_pipelines(device, &shader, *layer))
+            .collect();
+        Ok(mesh)
     }
 
-    fn update(&self, scene: &mut Scene, _device: &wgpu::Device, queue: &wgpu::Queue) {
-        if scene.point_lights.is_dirty() {
-            queue.write_buffer(
-                &self.point_lights_buffer,
-                0,
-                bytemuck::cast_slice(scene.point_lights.values.as_slice()),
-            );
+    fn update(&mut self, scene: &mut Scene, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut storage_group_outdated = self.shadows.update(device, queue, scene);
+        let object_models = Self::object_models(scene);
+        if self.front_face != scene.front_face
+            || self.visibility != scene.visibility
+            || self.layers != scene.layers
+            || self.object_models != object_models
+        {
+            self.static_bundle = None;
+        }
+        self.debug_mode = scene.globals.debug_mode;
+        self.front_face = scene.front_face;
+        self.visibility.clone_from(&scene.visibility);
+        self.layers.clone_from(&scene.layers);
+        self.object_models = object_models;
+        self.selected = scene.selected;
+        self.show_wireframe = scene.show_wireframe;
+        self.show_normals = scene.show_normals;
+        self.lod_levels = self
+            .object_models
+            .iter()
+            .enumerate()
+            .map(|(i, model)| scene.lod_level(i, self.lods.get(*model).map_or(0, Vec::len)))
+            .collect();
+
+        // Lights over the limit are never uploaded, so the previous ones stay
+        if let Err(err) = Self::check_light_counts(scene, device) {
+            error!("{}, keeping the previous lights", err);
             scene.point_lights.clear();
+            scene.directional_lights.clear();
         }
-        if scene.directional_lights.is_dirty() {
-            queue.write_buffer(
-                &self.directional_lights_buffer,
-                0,
-                bytemuck::cast_slice(scene.directional_lights.values.as_slice()),
+        if let Err(UploadError::CapacityExceeded { .. }) =
+            upload_vec_cache(queue, &self.point_lights_buffer, &mut scene.point_lights)
+        {
+            let capacity = Self::light_capacity::<PointLight>(device, scene.max_lights);
+            self.point_lights_buffer = create_storage_buffer::<PointLight>(
+                device,
+                Some(grown_capacity(scene.point_lights.len()).min(capacity as u64)),
             );
-            scene.directional_lights.clear();
+            self.culling
+                .set_point_lights(device, &self.point_lights_buffer);
+            storage_group_outdated = true;
         }
-        if scene.camera.is_dirty() {
-            queue.write_buffer(
-                &self.camera_buffer,
-                0,
-                bytemuck::bytes_of(&scene.camera.value.mesh_camera),
+        if let Err(UploadError::CapacityExceeded { .. }) = upload_vec_cache(
+            queue,
+            &self.directional_lights_buffer,
+            &mut scene.directional_lights,
+        ) {
+            let capacity = Self::light_capacity::<DirectionalLight>(device, scene.max_lights);
+            self.directional_lights_buffer = create_storage_buffer::<DirectionalLight>(
+                device,
+                Some(grown_capacity(scene.directional_lights.len()).min(capacity as u64)),
             );
-            scene.camera.clear();
-        }
-        if scene.objects.is_dirty() {
-            for (object, object_buffer) in zip(
-                scene.objects.values.iter().as_ref(),
-                self.object_buffers.iter().as_ref(),
-            ) {
-                queue.write_buffer(object_buffer, 0, bytemuck::bytes_of(object));
+            storage_group_outdated = true;
+        }
+        if storage_group_outdated {
+            self.static_bundle = None;
+            self.storage_group = Self::create_storage_group(
+                device,
+                &self.storage_group_layout,
+                (&self.point_lights_buffer, &self.directional_lights_buffer),
+                &self.globals_buffer,
+                &self.shadows,
+                &self.culling,
+                &self.material_sampler,
+            );
+        }
+
+        if let Err(UploadError::CapacityExceeded { .. }) =
+            upload_vec_cache_elements(queue, &self.object_buffers, &mut scene.objects)
+        {
+            while self.object_buffers.len() < scene.objects.len() {
+                let object_buffer = create_uniform_buffer::<Object>(device, None);
+                let uniform_group = Self::create_uniform_group(
+                    device,
+                    &self.uniform_group_layout,
+                    &self.camera_buffer,
+                    &object_buffer,
+                );
+                self.object_buffers.push(object_buffer);
+                self.uniform_groups.push(uniform_group);
+            }
+            self.static_bundle = None;
+        }
+        // Buffers of removed objects are dropped so they are never drawn
+        if self.object_buffers.len() > scene.objects.len() {
+            self.object_buffers.truncate(scene.objects.len());
+            self.uniform_groups.truncate(scene.objects.len());
+            self.static_bundle = None;
+        }
+        if self.static_bundle.is_none() {
+            self.static_bundle = Some(self.create_static_bundle(device));
+        }
+
+        let point_light_count = scene
+            .point_lights
+            .len()
+            .min((self.point_lights_buffer.size() / size_of::<PointLight>() as u64) as usize);
+        self.culling.update(queue, &scene.camera, point_light_count);
+        self.point_sprites
+            .update(queue, self.culling_size, self.viewport);
+        // The normal lines pipeline is only created once they are first shown
+        if self.show_normals && self.normal_lines.is_none() {
+            self.normal_lines = Some(NormalLines::new(
+                device,
+                self.format,
+                self.sample_count,
+                &self.uniform_group_layout,
+                self.reverse_z,
+                self.pipeline_cache.as_ref(),
+            ));
+        }
+        if let Some(normal_lines) = self.normal_lines.as_mut() {
+            normal_lines.update(queue, scene.normal_length, self.viewport);
+        }
+
+        debug_assert!(scene.camera.mesh_camera._padding == [0.0; 3]);
+        debug_assert!(scene.globals._padding == [0; 3]);
+        let results = [
+            upload_vec_cache(queue, &self.point_lights_buffer, &mut scene.point_lights),
+            upload_vec_cache(
+                queue,
+                &self.directional_lights_buffer,
+                &mut scene.directional_lights,
+            ),
+            upload_cache(queue, &self.camera_buffer, &mut scene.camera, |camera| {
+                &camera.mesh_camera
+            }),
+            upload_cache(queue, &self.globals_buffer, &mut scene.globals, |globals| {
+                globals
+            }),
+            upload_vec_cache_elements(queue, &self.object_buffers, &mut scene.objects),
+        ];
+
+        for result in results {
+            if let Err(err) = result {
+                error!("failed to upload mesh data: {}", err);
             }
-            scene.objects.clear();
         }
     }
 
@@ -336,29 +723,73 @@ impl Pipeline for Mesh {
         _: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
         view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
         depth_view: &wgpu::TextureView,
+        depth_load: wgpu::LoadOp<f32>,
     ) {
+        let casters = Casters {
+            object_groups: &self.uniform_groups,
+            vertex_buffers: &self.vertex_buffers,
+            index_buffers: &self.index_buffers,
+            index_lengths: &self.index_lengths,
+            index_formats: &self.index_formats,
+            visibility: &self.visibility,
+            object_models: &self.object_models,
+        };
+        self.shadows.draw(encoder, &casters);
+        self.culling.dispatch(encoder);
+
+        let color_load = match self.background.as_ref() {
+            Some(background) => {
+                background.draw(encoder, view, resolve_target, self.viewport);
+                wgpu::LoadOp::Load
+            }
+            None => viewport_load_op(self.viewport, clear_color(view.texture().format())),
+        };
+
+        // The wireframe and normal lines are depth tested against the shaded
+        // objects, which the G-buffer doesn't share with the color target, so they
+        // are drawn forward. So are layers, as the G-buffer only holds the world.
+        if let Some(deferred) = self.deferred.as_ref()
+            && !Self::needs_forward(self.debug_mode)
+            && !self.show_wireframe
+            && !self.show_normals
+            && self.layers.iter().all(|layer| *layer == Layer::World)
+        {
+            let mut render_pass =
+                deferred.begin_geometry_pass(encoder, self.front_face, self.viewport);
+            self.draw_objects(&mut render_pass);
+            drop(render_pass);
+            deferred.draw_lighting(
+                encoder,
+                view,
+                resolve_target,
+                &self.storage_group,
+                self.viewport,
+                color_load,
+            );
+            // The lit image has no depth of its own, so points only occlude each other
+            self.draw_points(encoder, view, resolve_target, depth_view, depth_load);
+            self.draw_overlay(encoder, view, resolve_target);
+            return;
+        }
+
         let render_pass_descriptor = wgpu::RenderPassDescriptor {
             label: Some("Mesh Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: view,
+                view,
                 depth_slice: None,
-                resolve_target: None,
+                resolve_target,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.003,
-                        g: 0.017,
-                        b: 0.032,
-                        a: 1.,
-                    }),
+                    load: color_load,
                     store: wgpu::StoreOp::Store,
                 },
             })],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: depth_view,
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: wgpu::StoreOp::Discard,
+                    load: depth_load,
+                    store: wgpu::StoreOp::Store,
                 }),
                 stencil_ops: None,
             }),
@@ -366,16 +797,1321 @@ impl Pipeline for Mesh {
         };
 
         let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
-        render_pass.set_pipeline(&self.pipeline);
-        for i in 0..self.vertex_buffers.len() {
-            let vertex_buffer = self.vertex_buffers.get(i).unwrap();
-            let index_buffer = self.index_buffers.get(i).unwrap();
+        set_viewport(&mut render_pass, self.viewport);
+        for layer in Layer::ALL {
+            if layer == Layer::World
+                && let Some(static_bundle) = self.static_bundle.as_ref()
+            {
+                render_pass.execute_bundles([static_bundle]);
+            }
+            render_pass.set_pipeline(self.forward_pipeline(layer));
+            for i in 0..self.object_models.len() {
+                if self.layer(i) == layer
+                    && self.is_visible(i)
+                    && (self.static_bundle.is_none() || !self.is_static(i))
+                {
+                    self.draw_object(&mut render_pass, i);
+                }
+            }
+        }
+        drop(render_pass);
+        self.draw_wireframe(encoder, view, resolve_target, depth_view);
+        self.draw_normals(encoder, view, resolve_target, depth_view);
+        self.draw_points(
+            encoder,
+            view,
+            resolve_target,
+            depth_view,
+            wgpu::LoadOp::Load,
+        );
+        self.draw_overlay(encoder, view, resolve_target);
+    }
+}
+
+impl Mesh {
+    pub fn set_point_shadow_resolution(&mut self, resolution: u32) {
+        self.shadows.point.set_resolution(resolution);
+    }
+
+    pub fn set_shadow_cascades(&mut self, cascade_count: u32, split_lambda: f32) {
+        self.shadows
+            .directional
+            .set_cascades(cascade_count, split_lambda);
+    }
+
+    pub fn set_directional_shadow_count(&mut self, shadow_count: u32) {
+        self.shadows.directional.set_shadow_count(shadow_count);
+    }
 
-            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.set_bind_group(0, self.uniform_groups.get(i), &[]);
-            render_pass.set_bind_group(1, &self.storage_group, &[]);
-            render_pass.draw_indexed(0..self.index_lengths.get(i).unwrap().clone(), 0, 0..1);
+    pub fn set_point_size(&mut self, point_size: f32) {
+        self.point_sprites.set_point_size(point_size);
+    }
+
+    pub fn set_anisotropy(&mut self, device: &wgpu::Device, anisotropy: u16) {
+        if anisotropy == self.anisotropy {
+            return;
+        }
+
+        self.anisotropy = anisotropy;
+        self.material_sampler = create_material_sampler(device, anisotropy);
+        self.static_bundle = None;
+        self.storage_group = Self::create_storage_group(
+            device,
+            &self.storage_group_layout,
+            (&self.point_lights_buffer, &self.directional_lights_buffer),
+            &self.globals_buffer,
+            &self.shadows,
+            &self.culling,
+            &self.material_sampler,
+        );
+    }
+
+    pub fn model_count(&self) -> usize {
+        self.vertex_buffers.len()
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.object_buffers.len()
+    }
+
+    pub fn add_model(&mut self, model: &Model, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let (vertex_buffer, index_buffer, index_length) =
+            Self::create_model_buffer(model, device, queue);
+        self.vertex_buffers.push(vertex_buffer);
+        self.index_buffers.push(index_buffer);
+        self.index_lengths.push(index_length);
+        self.index_formats.push(model.index_format);
+        self.material_groups.push(Self::create_material_groups(
+            model,
+            device,
+            queue,
+            &self.material_bindings,
+        ));
+        self.lods.push(Self::create_lods(
+            model,
+            device,
+            queue,
+            &self.material_bindings,
+        ));
+        self.point_counts.push(Self::point_count(model));
+        self.static_bundle = None;
+    }
+
+    pub fn set_shading(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        shading: Shading,
+    ) {
+        self.deferred = match shading {
+            Shading::Forward => None,
+            Shading::Deferred => Some(Deferred::new(
+                device,
+                config,
+                &self.pipeline_layout,
+                &self.storage_group_layout,
+                &self.camera_buffer,
+                DeferredOptions {
+                    sample_count: self.sample_count,
+                    reverse_z: self.reverse_z,
+                    depth_bias: self.depth_bias,
+                    cache: self.pipeline_cache.as_ref(),
+                },
+            )),
+        };
+    }
+
+    pub fn set_depth_bias(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_bias: wgpu::DepthBiasState,
+    ) {
+        if depth_bias == self.depth_bias {
+            return;
+        }
+
+        self.depth_bias = depth_bias;
+        let shader = create_shader(device);
+        self.static_bundle = None;
+        self.pipelines = Layer::ALL
+            .iter()
+            .map(|layer| self.create_layer_pipelines(device, &shader, *layer))
+            .collect();
+        if self.deferred.is_some() {
+            self.set_shading(device, config, Shading::Deferred);
+        }
+    }
+
+    pub fn set_layer_state(&mut self, device: &wgpu::Device, layer: Layer, state: LayerState) {
+        if state == self.layer_states[layer as usize] {
+            return;
+        }
+
+        self.layer_states[layer as usize] = state;
+        let shader = create_shader(device);
+        self.static_bundle = None;
+        self.pipelines[layer as usize] = self.create_layer_pipelines(device, &shader, layer);
+    }
+
+    pub fn set_background(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        gradient: Option<Gradient>,
+    ) {
+        self.background = gradient.map(|gradient| {
+            Background::new(
+                device,
+                queue,
+                config,
+                self.sample_count,
+                gradient,
+                self.pipeline_cache.as_ref(),
+            )
+        });
+    }
+
+    // The light culling grid covers one viewport, offset to where it is drawn
+    pub fn set_viewport(&mut self, device: &wgpu::Device, viewport: Option<Viewport>) {
+        let culling_size = viewport.map_or(self.size, |viewport| (viewport.width, viewport.height));
+        if culling_size != self.culling_size {
+            self.culling_size = culling_size;
+            self.culling.resize(
+                device,
+                &self.point_lights_buffer,
+                culling_size.0,
+                culling_size.1,
+            );
+            self.static_bundle = None;
+            self.storage_group = Self::create_storage_group(
+                device,
+                &self.storage_group_layout,
+                (&self.point_lights_buffer, &self.directional_lights_buffer),
+                &self.globals_buffer,
+                &self.shadows,
+                &self.culling,
+                &self.material_sampler,
+            );
+        }
+
+        let (x, y) = viewport.map_or((0, 0), |viewport| (viewport.x, viewport.y));
+        self.culling.set_origin(x, y);
+        self.viewport = viewport;
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.size = (width, height);
+        self.culling_size = (width, height);
+        self.culling
+            .resize(device, &self.point_lights_buffer, width, height);
+        self.static_bundle = None;
+        self.storage_group = Self::create_storage_group(
+            device,
+            &self.storage_group_layout,
+            (&self.point_lights_buffer, &self.directional_lights_buffer),
+            &self.globals_buffer,
+            &self.shadows,
+            &self.culling,
+            &self.material_sampler,
+        );
+        if let Some(deferred) = self.deferred.as_mut() {
+            deferred.resize(device, width, height);
+        }
+    }
+
+    // The G-buffer only keeps what lighting needs, so debug views that
+    // read UVs or screen-space derivatives fall back to forward shading.
+    fn needs_forward(debug_mode: u32) -> bool {
+        [DebugMode::Uvs, DebugMode::Tangents, DebugMode::Bitangents]
+            .iter()
+            .any(|mode| *mode as u32 == debug_mode)
+    }
+
+    fn forward_pipeline(&self, layer: Layer) -> &wgpu::RenderPipeline {
+        let (pipeline, flipped_pipeline) = &self.pipelines[layer as usize];
+        match self.front_face {
+            wgpu::FrontFace::Ccw => pipeline,
+            wgpu::FrontFace::Cw => flipped_pipeline,
         }
     }
+
+    // Objects whose model hasn't been loaded yet are never drawn
+    fn is_visible(&self, index: usize) -> bool {
+        self.visibility.get(index) != Some(&false)
+            && self
+                .object_models
+                .get(index)
+                .is_some_and(|model| *model < self.vertex_buffers.len())
+    }
+
+    fn object_models(scene: &Scene) -> Vec<usize> {
+        (0..scene.objects.len())
+            .map(|i| scene.object_model(i))
+            .collect()
+    }
+
+    fn layer(&self, index: usize) -> Layer {
+        self.layers.get(index).copied().unwrap_or_default()
+    }
+
+    // World objects without LODs always draw the same buffers, so their forward
+    // draws are recorded once into a bundle. Objects with LODs can switch levels
+    // every frame and are drawn one by one, as are the few on the other layers.
+    fn is_static(&self, index: usize) -> bool {
+        self.layer(index) == Layer::World
+            && self
+                .lods
+                .get(self.object_models[index])
+                .is_none_or(Vec::is_empty)
+    }
+
+    // Object transforms live in uniform buffers the bundle reads when it is
+    // executed, so it only has to be recreated when the set of draws changes
+    fn create_static_bundle(&self, device: &wgpu::Device) -> wgpu::RenderBundle {
+        let bundle_encoder_descriptor = wgpu::RenderBundleEncoderDescriptor {
+            label: Some("Static Mesh Render Bundle Encoder"),
+            color_formats: &[Some(self.format)],
+            depth_stencil: Some(wgpu::RenderBundleDepthStencil {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_read_only: false,
+                stencil_read_only: true,
+            }),
+            sample_count: self.sample_count,
+            multiview: None,
+        };
+        let mut bundle_encoder = device.create_render_bundle_encoder(&bundle_encoder_descriptor);
+        bundle_encoder.set_pipeline(self.forward_pipeline(Layer::World));
+        for i in 0..self.object_models.len() {
+            if self.is_visible(i) && self.is_static(i) {
+                self.draw_object(&mut bundle_encoder, i);
+            }
+        }
+
+        let bundle_descriptor = wgpu::RenderBundleDescriptor {
+            label: Some("Static Mesh Render Bundle"),
+        };
+        bundle_encoder.finish(&bundle_descriptor)
+    }
+
+    fn draw_objects<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        for i in 0..self.object_models.len() {
+            if self.is_visible(i) {
+                self.draw_object(render_pass, i);
+            }
+        }
+    }
+
+    fn draw_object<'a>(&'a self, encoder: &mut impl RenderEncoder<'a>, i: usize) {
+        let model = self.object_models[i];
+        if self.index_lengths[model] == 0 {
+            return;
+        }
+        let lod = self
+            .lod_levels
+            .get(i)
+            .and_then(|level| level.checked_sub(1))
+            .and_then(|level| self.lods[model].get(level));
+        let (vertex_buffer, index_buffer, index_format, material_groups) = match lod {
+            Some(lod) => (
+                &lod.vertex_buffer,
+                &lod.index_buffer,
+                lod.index_format,
+                &lod.material_groups,
+            ),
+            None => (
+                &self.vertex_buffers[model],
+                &self.index_buffers[model],
+                self.index_formats[model],
+                &self.material_groups[model],
+            ),
+        };
+
+        encoder.set_vertex_buffer(0, vertex_buffer.slice(..));
+        encoder.set_index_buffer(index_buffer.slice(..), index_format);
+        encoder.set_bind_group(0, self.uniform_groups.get(i), &[]);
+        encoder.set_bind_group(1, Some(&self.storage_group), &[]);
+        for (indices, material_group) in material_groups {
+            encoder.set_bind_group(2, Some(material_group), &[]);
+            encoder.draw_indexed(indices.clone(), 0, 0..1);
+        }
+    }
+
+    fn draw_wireframe(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        depth_view: &wgpu::TextureView,
+    ) {
+        let (Some(wireframe_pipeline), true) = (&self.wireframe_pipeline, self.show_wireframe)
+        else {
+            return;
+        };
+
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Wireframe Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            ..Default::default()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
+        set_viewport(&mut render_pass, self.viewport);
+        render_pass.set_pipeline(wireframe_pipeline);
+        self.draw_objects(&mut render_pass);
+    }
+
+    fn draw_normals(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        depth_view: &wgpu::TextureView,
+    ) {
+        let (Some(normal_lines), true) = (&self.normal_lines, self.show_normals) else {
+            return;
+        };
+
+        // Empty models are padded to one vertex that has no normal to show
+        let sources: Vec<NormalSource> = (0..self.object_models.len())
+            .filter(|i| self.is_visible(*i))
+            .filter(|i| {
+                let model = self.object_models[*i];
+                self.index_lengths[model] > 0 || self.point_counts[model] > 0
+            })
+            .filter_map(|i| {
+                let vertex_buffer = &self.vertex_buffers[self.object_models[i]];
+                let vertex_count = vertex_buffer.size() / size_of::<Vertex>() as u64;
+                Some((
+                    vertex_buffer,
+                    vertex_count as u32,
+                    self.uniform_groups.get(i)?,
+                ))
+            })
+            .collect();
+        normal_lines.draw(encoder, view, resolve_target, depth_view, &sources);
+    }
+
+    fn draw_points(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        depth_view: &wgpu::TextureView,
+        depth_load: wgpu::LoadOp<f32>,
+    ) {
+        let point_clouds: Vec<PointCloud> = (0..self.object_models.len())
+            .filter(|i| self.is_visible(*i) && self.point_counts[self.object_models[*i]] > 0)
+            .filter_map(|i| {
+                let model = self.object_models[i];
+                Some((
+                    &self.vertex_buffers[model],
+                    self.point_counts[model],
+                    self.uniform_groups.get(i)?,
+                ))
+            })
+            .collect();
+        self.point_sprites.draw(
+            encoder,
+            view,
+            resolve_target,
+            depth_view,
+            depth_load,
+            &point_clouds,
+        );
+    }
+
+    fn draw_overlay(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+    ) {
+        let (Some(overlay_pipeline), Some(selected)) = (&self.overlay_pipeline, self.selected)
+        else {
+            return;
+        };
+        if !self.is_visible(selected) || self.index_lengths[self.object_models[selected]] == 0 {
+            return;
+        }
+        let model = self.object_models[selected];
+
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Selection Overlay Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
+        set_viewport(&mut render_pass, self.viewport);
+        render_pass.set_pipeline(overlay_pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffers[model].slice(..));
+        render_pass.set_index_buffer(
+            self.index_buffers[model].slice(..),
+            self.index_formats[model],
+        );
+        render_pass.set_bind_group(0, &self.uniform_groups[selected], &[]);
+        render_pass.set_bind_group(1, &self.storage_group, &[]);
+        render_pass.set_bind_group(2, &self.material_groups[model][0].1, &[]);
+        render_pass.draw_indexed(0..self.index_lengths[model], 0, 0..1);
+    }
+
+    pub fn rebuild(
+        &mut self,
+        init_data: &InitData,
+        scene: &Scene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let (vertex_buffers, index_buffers, index_lengths, index_formats) =
+            Self::create_model_buffers(init_data, device, queue);
+        let material_groups = init_data
+            .models
+            .iter()
+            .map(|model| {
+                Self::create_material_groups(model, device, queue, &self.material_bindings)
+            })
+            .collect();
+        let lods = init_data
+            .models
+            .iter()
+            .map(|model| Self::create_lods(model, device, queue, &self.material_bindings))
+            .collect();
+        let (object_buffers, uniform_groups) = Self::create_object_buffers(
+            scene,
+            device,
+            &self.uniform_group_layout,
+            &self.camera_buffer,
+        );
+        let (point_lights_buffer, directional_lights_buffer) =
+            Self::create_light_buffers(scene, device);
+        self.culling.set_point_lights(device, &point_lights_buffer);
+        self.storage_group = Self::create_storage_group(
+            device,
+            &self.storage_group_layout,
+            (&point_lights_buffer, &directional_lights_buffer),
+            &self.globals_buffer,
+            &self.shadows,
+            &self.culling,
+            &self.material_sampler,
+        );
+
+        self.vertex_buffers = vertex_buffers;
+        self.index_buffers = index_buffers;
+        self.index_lengths = index_lengths;
+        self.index_formats = index_formats;
+        self.material_groups = material_groups;
+        self.lods = lods;
+        self.point_counts = init_data.models.iter().map(Self::point_count).collect();
+        self.object_buffers = object_buffers;
+        self.uniform_groups = uniform_groups;
+        self.point_lights_buffer = point_lights_buffer;
+        self.directional_lights_buffer = directional_lights_buffer;
+        self.static_bundle = None;
+    }
+
+    fn depth_stencil_state(
+        reverse_z: bool,
+        depth_bias: wgpu::DepthBiasState,
+        layer_state: LayerState,
+    ) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_compare: if !layer_state.depth_test {
+                wgpu::CompareFunction::Always
+            } else if reverse_z {
+                wgpu::CompareFunction::GreaterEqual
+            } else {
+                wgpu::CompareFunction::LessEqual
+            },
+            depth_write_enabled: layer_state.depth_write,
+            stencil: wgpu::StencilState {
+                ..Default::default()
+            },
+            bias: oriented_depth_bias(depth_bias, reverse_z),
+        }
+    }
+
+    fn create_layer_pipelines(
+        &self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layer: Layer,
+    ) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+        let layer_state = self.layer_states[layer as usize];
+        let color_state_target = [Some(wgpu::ColorTargetState {
+            format: self.format,
+            blend: Some(layer_state.blend),
+            write_mask: wgpu::ColorWrites::all(),
+        })];
+        Self::create_pipelines(
+            device,
+            shader,
+            &self.pipeline_layout,
+            &color_state_target,
+            self.sample_count,
+            Self::depth_stencil_state(self.reverse_z, self.depth_bias, layer_state),
+            self.pipeline_cache.as_ref(),
+        )
+    }
+
+    fn create_pipelines(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        color_state_target: &[Option<wgpu::ColorTargetState>],
+        sample_count: u32,
+        depth_stencil_state: wgpu::DepthStencilState,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            ..Default::default()
+        };
+        let mut pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+            label: Some("Mesh Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vert_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[Vertex::LAYOUT],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(depth_stencil_state),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("frag_main"),
+                compilation_options: compilation_options.clone(),
+                targets: color_state_target,
+            }),
+            multiview: None,
+            cache,
+        };
+
+        let pipeline = device.create_render_pipeline(&pipeline_descriptor);
+        pipeline_descriptor.label = Some("Flipped Mesh Pipeline");
+        pipeline_descriptor.primitive.front_face = wgpu::FrontFace::Cw;
+        let flipped_pipeline = device.create_render_pipeline(&pipeline_descriptor);
+
+        (pipeline, flipped_pipeline)
+    }
+
+    fn create_overlay_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        color_state_target: &[Option<wgpu::ColorTargetState>],
+        sample_count: u32,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Option<wgpu::RenderPipeline> {
+        if !device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+        {
+            warn!("POLYGON_MODE_LINE is not supported, disabling the selection overlay");
+            return None;
+        }
+
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            ..Default::default()
+        };
+        let pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+            label: Some("Selection Overlay Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vert_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[Vertex::LAYOUT],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                polygon_mode: wgpu::PolygonMode::Line,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("overlay_main"),
+                compilation_options: compilation_options.clone(),
+                targets: color_state_target,
+            }),
+            multiview: None,
+            cache,
+        };
+
+        Some(device.create_render_pipeline(&pipeline_descriptor))
+    }
+
+    // Lines are pulled toward the camera so they win the depth test against the
+    // faces they outline
+    fn create_wireframe_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        color_state_target: &[Option<wgpu::ColorTargetState>],
+        sample_count: u32,
+        reverse_z: bool,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Option<wgpu::RenderPipeline> {
+        if !device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+        {
+            warn!("POLYGON_MODE_LINE is not supported, disabling the wireframe");
+            return None;
+        }
+
+        let direction = if reverse_z { 1 } else { -1 };
+        let depth_stencil_state = wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_compare: if reverse_z {
+                wgpu::CompareFunction::GreaterEqual
+            } else {
+                wgpu::CompareFunction::LessEqual
+            },
+            depth_write_enabled: false,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState {
+                constant: direction * WIREFRAME_DEPTH_BIAS,
+                slope_scale: direction as f32 * WIREFRAME_SLOPE_BIAS,
+                clamp: 0.0,
+            },
+        };
+
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            ..Default::default()
+        };
+        let pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+            label: Some("Wireframe Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vert_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[Vertex::LAYOUT],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                polygon_mode: wgpu::PolygonMode::Line,
+                ..Default::default()
+            },
+            depth_stencil: Some(depth_stencil_state),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("wireframe_main"),
+                compilation_options: compilation_options.clone(),
+                targets: color_state_target,
+            }),
+            multiview: None,
+            cache,
+        };
+
+        Some(device.create_render_pipeline(&pipeline_descriptor))
+    }
+
+    fn create_model_buffers(
+        init_data: &InitData,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> (
+        Vec<wgpu::Buffer>,
+        Vec<wgpu::Buffer>,
+        Vec<u32>,
+        Vec<wgpu::IndexFormat>,
+    ) {
+        let mut vertex_buffers = Vec::new();
+        let mut index_buffers = Vec::new();
+        let mut index_lengths = Vec::new();
+        let mut index_formats = Vec::new();
+        for model in &init_data.models {
+            let (vertex_buffer, index_buffer, index_length) =
+                Self::create_model_buffer(model, device, queue);
+            vertex_buffers.push(vertex_buffer);
+            index_buffers.push(index_buffer);
+            index_lengths.push(index_length);
+            index_formats.push(model.index_format);
+        }
+
+        (vertex_buffers, index_buffers, index_lengths, index_formats)
+    }
+
+    fn create_model_buffer(
+        model: &Model,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> (wgpu::Buffer, wgpu::Buffer, u32) {
+        // Empty models still get a vertex, since an empty buffer cannot be sliced
+        let buffer_descriptor = wgpu::BufferDescriptor {
+            label: Some("Vertex Buffer"),
+            size: (model.vertex_buffer.len().max(1) * std::mem::size_of::<Vertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        };
+        let vertex_buffer = device.create_buffer(&buffer_descriptor);
+        queue.write_buffer(
+            &vertex_buffer,
+            0,
+            bytemuck::cast_slice(model.vertex_buffer.as_slice()),
+        );
+
+        let short_indices: Vec<u16>;
+        let index_data: &[u8] = match model.index_format {
+            wgpu::IndexFormat::Uint16 => {
+                // Pad to an even count since buffer writes must be a multiple of four bytes
+                let padded_len = model.index_buffer.len().next_multiple_of(2);
+                short_indices = model
+                    .index_buffer
+                    .iter()
+                    .map(|index| *index as u16)
+                    .chain(std::iter::repeat(0))
+                    .take(padded_len)
+                    .collect();
+                bytemuck::cast_slice(short_indices.as_slice())
+            }
+            wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(model.index_buffer.as_slice()),
+        };
+
+        // Point clouds have no indices, but an empty buffer cannot be bound
+        let buffer_descriptor = wgpu::BufferDescriptor {
+            label: Some("Index Buffer"),
+            size: index_data.len().max(4) as u64,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        };
+        let index_buffer = device.create_buffer(&buffer_descriptor);
+        queue.write_buffer(&index_buffer, 0, index_data);
+        queue.submit(vec![]);
+
+        (vertex_buffer, index_buffer, model.index_buffer.len() as u32)
+    }
+
+    // Models without material groups are drawn whole with the default material
+    fn create_material_groups(
+        model: &Model,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bindings: &MaterialBindings,
+    ) -> Vec<(std::ops::Range<u32>, wgpu::BindGroup)> {
+        let default_group = [MaterialGroup {
+            start: 0,
+            count: model.index_buffer.len() as u32,
+            material: Material::DEFAULT,
+            texture: None,
+        }];
+        let material_groups = if model.material_groups.is_empty() {
+            &default_group[..]
+        } else {
+            &model.material_groups[..]
+        };
+
+        let textures: Vec<wgpu::TextureView> = model
+            .textures
+            .iter()
+            .map(|texture| create_material_texture(device, queue, texture))
+            .collect();
+
+        material_groups
+            .iter()
+            .map(|material_group| {
+                let material_buffer = create_uniform_buffer::<Material>(device, None);
+                queue.write_buffer(
+                    &material_buffer,
+                    0,
+                    bytemuck::bytes_of(&material_group.material),
+                );
+                let texture = material_group
+                    .texture
+                    .and_then(|index| textures.get(index))
+                    .unwrap_or(&bindings.default_texture);
+                let group_descriptor = wgpu::BindGroupDescriptor {
+                    label: Some("Mesh Material Bind Group"),
+                    layout: &bindings.layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::Buffer(
+                                material_buffer.as_entire_buffer_binding(),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(texture),
+                        },
+                    ],
+                };
+                let indices = material_group.start..material_group.start + material_group.count;
+                (indices, device.create_bind_group(&group_descriptor))
+            })
+            .collect()
+    }
+
+    fn point_count(model: &Model) -> u32 {
+        if model.is_point_cloud() {
+            model.vertex_buffer.len() as u32
+        } else {
+            0
+        }
+    }
+
+    // Shadows and the selection overlay keep using the full detail model
+    fn create_lods(
+        model: &Model,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bindings: &MaterialBindings,
+    ) -> Vec<Lod> {
+        model
+            .lods
+            .iter()
+            .map(|lod| {
+                let (vertex_buffer, index_buffer, _) =
+                    Self::create_model_buffer(lod, device, queue);
+                Lod {
+                    vertex_buffer,
+                    index_buffer,
+                    index_format: lod.index_format,
+                    material_groups: Self::create_material_groups(lod, device, queue, bindings),
+                }
+            })
+            .collect()
+    }
+
+    fn create_object_buffers(
+        scene: &Scene,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        camera_buffer: &wgpu::Buffer,
+    ) -> (Vec<wgpu::Buffer>, Vec<wgpu::BindGroup>) {
+        let mut object_buffers = Vec::new();
+        for _ in scene.objects.values() {
+            let object_buffer = create_uniform_buffer::<Object>(device, None);
+            object_buffers.push(object_buffer);
+        }
+
+        let mut uniform_groups = Vec::new();
+        for object_buffer in &object_buffers {
+            let uniform_group =
+                Self::create_uniform_group(device, layout, camera_buffer, object_buffer);
+            uniform_groups.push(uniform_group);
+        }
+
+        (object_buffers, uniform_groups)
+    }
+
+    // How many lights of one kind fit in a single storage buffer binding
+    fn light_capacity<T>(device: &wgpu::Device, max_lights: usize) -> usize {
+        let binding_size = device.limits().max_storage_buffer_binding_size as usize;
+        max_lights.min(binding_size / size_of::<T>())
+    }
+
+    fn check_light_counts(scene: &Scene, device: &wgpu::Device) -> Result<(), MeshError> {
+        let counts = [
+            (
+                "point",
+                scene.point_lights.len(),
+                Self::light_capacity::<PointLight>(device, scene.max_lights),
+            ),
+            (
+                "directional",
+                scene.directional_lights.len(),
+                Self::light_capacity::<DirectionalLight>(device, scene.max_lights),
+            ),
+        ];
+        for (kind, count, max) in counts {
+            if count > max {
+                return Err(MeshError::TooManyLights { kind, count, max });
+            }
+        }
+        Ok(())
+    }
+
+    fn create_light_buffers(scene: &Scene, device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
+        let point_lights_buffer = create_storage_buffer::<PointLight>(
+            device,
+            Some(scene.point_lights.len().max(1) as u64),
+        );
+        let directional_lights_buffer = create_storage_buffer::<DirectionalLight>(
+            device,
+            Some(scene.directional_lights.len().max(1) as u64),
+        );
+
+        (point_lights_buffer, directional_lights_buffer)
+    }
+
+    fn create_uniform_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        camera_buffer: &wgpu::Buffer,
+        object_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        let uniform_group_descriptor = wgpu::BindGroupDescriptor {
+            label: Some("Mesh Uniform Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(
+                        camera_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(
+                        object_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+            ],
+        };
+
+        device.create_bind_group(&uniform_group_descriptor)
+    }
+
+    fn create_storage_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        (point_lights_buffer, directional_lights_buffer): (&wgpu::Buffer, &wgpu::Buffer),
+        globals_buffer: &wgpu::Buffer,
+        shadows: &Shadows,
+        culling: &LightCulling,
+        material_sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        let storage_group_descriptor = wgpu::BindGroupDescriptor {
+            label: Some("Mesh Storage Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(
+                        point_lights_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(
+                        directional_lights_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(
+                        globals_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(shadows.point.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&shadows.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(shadows.directional.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Buffer(
+                        shadows
+                            .directional
+                            .cascades_buffer()
+                            .as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::Buffer(
+                        culling.tile_lights_buffer().as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::Buffer(
+                        culling.tiles_buffer().as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: wgpu::BindingResource::Sampler(material_sampler),
+                },
+            ],
+        };
+
+        device.create_bind_group(&storage_group_descriptor)
+    }
+}
+
+impl MaterialBindings {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> MaterialBindings {
+        let layout_descriptor = wgpu::BindGroupLayoutDescriptor {
+            label: Some("Mesh Material Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        };
+
+        let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+        MaterialBindings {
+            layout: device.create_bind_group_layout(&layout_descriptor),
+            default_texture: create_material_texture(device, queue, &white),
+        }
+    }
+}
+
+// Anisotropic filtering requires every filter to be linear
+fn create_material_sampler(device: &wgpu::Device, anisotropy: u16) -> wgpu::Sampler {
+    let sampler_descriptor = wgpu::SamplerDescriptor {
+        label: Some("Mesh Material Sampler"),
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        anisotropy_clamp: anisotropy.clamp(1, MAX_ANISOTROPY),
+        ..Default::default()
+    };
+    device.create_sampler(&sampler_descriptor)
+}
+
+fn create_material_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &image::RgbaImage,
+) -> wgpu::TextureView {
+    let size = wgpu::Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1,
+    };
+    let texture_descriptor = wgpu::TextureDescriptor {
+        label: Some("Mesh Material Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    };
+    let texture = device.create_texture(&texture_descriptor);
+    queue.write_texture(
+        texture.as_image_copy(),
+        image,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * image.width()),
+            rows_per_image: Some(image.height()),
+        },
+        size,
+    );
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+pub fn create_shader(device: &wgpu::Device) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Mesh Shader"),
+        source: wgpu::ShaderSource::Wgsl(
+            concat!(
+                include_str!("../shaders/lighting.wgsl"),
+                include_str!("../shaders/mesh.wgsl")
+            )
+            .into(),
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TEST_SIZE, test_config, test_device, test_scene, test_target};
+
+    fn test_mesh(model_count: usize) -> (wgpu::Device, wgpu::Queue, Scene, Mesh) {
+        let (device, queue) = pollster::block_on(test_device()).unwrap();
+        let (init_data, scene) = test_scene(model_count);
+        let config = test_config(TEST_SIZE.0, TEST_SIZE.1);
+        let mesh = Mesh::new(&init_data, &scene, &device, &queue, &config, 1, None).unwrap();
+        (device, queue, scene, mesh)
+    }
+
+    #[test]
+    fn builds_against_the_test_format() {
+        let (_, _, _, mesh) = test_mesh(2);
+        assert_eq!(mesh.model_count(), 2);
+        assert_eq!(mesh.object_count(), 2);
+    }
+
+    // Uploads and records a frame of the scene Mesh::new and Mesh::update read,
+    // so any mismatch with the Scene's fields fails here as well as in the build
+    #[test]
+    fn renders_a_frame() {
+        let (device, queue, mut scene, mut mesh) = test_mesh(2);
+        let (view, depth_view) = test_target(&device);
+        mesh.update(&mut scene, &device, &queue);
+        assert!(!scene.is_dirty());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        mesh.draw(
+            &device,
+            &mut encoder,
+            &view,
+            None,
+            &depth_view,
+            wgpu::LoadOp::Clear(1.0),
+        );
+        queue.submit([encoder.finish()]);
+        device.poll(wgpu::PollType::Wait).unwrap();
+    }
+
+    // Samplers with anisotropy are only valid with every filter linear
+    #[test]
+    fn renders_with_anisotropy() {
+        let (device, queue, mut scene, mut mesh) = test_mesh(1);
+        let (view, depth_view) = test_target(&device);
+        mesh.set_anisotropy(&device, MAX_ANISOTROPY);
+        mesh.update(&mut scene, &device, &queue);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        mesh.draw(
+            &device,
+            &mut encoder,
+            &view,
+            None,
+            &depth_view,
+            wgpu::LoadOp::Clear(1.0),
+        );
+        queue.submit([encoder.finish()]);
+        device.poll(wgpu::PollType::Wait).unwrap();
+    }
+
+    #[test]
+    fn grows_the_point_lights_buffer() {
+        let (device, queue, mut scene, mut mesh) = test_mesh(1);
+        let stride = size_of::<PointLight>() as u64;
+        assert_eq!(mesh.point_lights_buffer.size(), stride);
+
+        let light = scene.point_lights.values()[0];
+        for _ in 0..4 {
+            scene.point_lights.push(light);
+        }
+        mesh.update(&mut scene, &device, &queue);
+        assert!(!scene.point_lights.is_dirty());
+        assert_eq!(mesh.point_lights_buffer.size(), grown_capacity(5) * stride);
+    }
+
+    #[test]
+    fn adds_and_removes_object_buffers() {
+        let (device, queue, mut scene, mut mesh) = test_mesh(1);
+        mesh.update(&mut scene, &device, &queue);
+        let count = mesh.object_count();
+
+        let transform = crate::scene::Transform::from_matrix(cgmath::Matrix4::from_scale(2.0));
+        let index = scene.add_object(0, transform, 0.5);
+        mesh.update(&mut scene, &device, &queue);
+        assert_eq!(index, count);
+        assert_eq!(mesh.object_count(), count + 1);
+        assert_eq!(mesh.object_models[index], 0);
+
+        scene.remove_object(index);
+        mesh.update(&mut scene, &device, &queue);
+        assert_eq!(mesh.object_count(), count);
+    }
+
+    // Empty models can't be sliced or drawn, but objects using them must not
+    // stop the rest of the frame from rendering
+    #[test]
+    fn renders_an_empty_model() {
+        let (device, queue, mut scene, mut mesh) = test_mesh(1);
+        let (view, depth_view) = test_target(&device);
+        let empty = Model {
+            vertex_buffer: Vec::new(),
+            index_buffer: Vec::new(),
+            index_format: wgpu::IndexFormat::Uint16,
+            material_groups: Vec::new(),
+            textures: Vec::new(),
+            lods: Vec::new(),
+        };
+        mesh.add_model(&empty, &device, &queue);
+        let transform = crate::scene::Transform::from_matrix(cgmath::Matrix4::from_scale(1.0));
+        scene.selected = Some(scene.add_object(1, transform, 0.5));
+        mesh.update(&mut scene, &device, &queue);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        mesh.draw(
+            &device,
+            &mut encoder,
+            &view,
+            None,
+            &depth_view,
+            wgpu::LoadOp::Clear(1.0),
+        );
+        queue.submit([encoder.finish()]);
+        device.poll(wgpu::PollType::Wait).unwrap();
+    }
 }
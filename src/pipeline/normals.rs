@@ -0,0 +1,184 @@
+use super::mesh::Vertex;
+use super::{Viewport, create_uniform_buffer, set_viewport};
+
+pub const NORMAL_LENGTH: f32 = 0.05;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Lines {
+    pub length: f32,
+    pub _padding: [f32; 3],
+}
+
+// Each vertex is read once per line, which runs from the vertex along its normal
+const LINE_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+    array_stride: size_of::<Vertex>() as wgpu::BufferAddress,
+    step_mode: wgpu::VertexStepMode::Instance,
+    attributes: Vertex::LAYOUT.attributes,
+};
+
+// A model whose normals are drawn: its vertex buffer, vertex count and object bind group
+pub type NormalSource<'a> = (&'a wgpu::Buffer, u32, &'a wgpu::BindGroup);
+
+pub struct NormalLines {
+    pipeline: wgpu::RenderPipeline,
+    lines_buffer: wgpu::Buffer,
+    group: wgpu::BindGroup,
+    viewport: Option<Viewport>,
+}
+
+impl NormalLines {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        object_group_layout: &wgpu::BindGroupLayout,
+        reverse_z: bool,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> NormalLines {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/normals.wgsl"));
+        let color_state_target = [Some(wgpu::ColorTargetState {
+            format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::all(),
+        })];
+
+        let group_layout_descriptor = wgpu::BindGroupLayoutDescriptor {
+            label: Some("Normal Lines Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        };
+        let group_layout = device.create_bind_group_layout(&group_layout_descriptor);
+
+        let lines_buffer = create_uniform_buffer::<Lines>(device, None);
+        let group_descriptor = wgpu::BindGroupDescriptor {
+            label: Some("Normal Lines Bind Group"),
+            layout: &group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(lines_buffer.as_entire_buffer_binding()),
+            }],
+        };
+        let group = device.create_bind_group(&group_descriptor);
+
+        let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
+            label: Some("Normal Lines Pipeline Layout"),
+            bind_group_layouts: &[object_group_layout, &group_layout],
+            push_constant_ranges: &[],
+        };
+        let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_descriptor);
+
+        let depth_stencil_state = wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_compare: if reverse_z {
+                wgpu::CompareFunction::GreaterEqual
+            } else {
+                wgpu::CompareFunction::LessEqual
+            },
+            depth_write_enabled: false,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        };
+
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            ..Default::default()
+        };
+        let pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+            label: Some("Normal Lines Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vert_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[LINE_LAYOUT],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: Some(depth_stencil_state),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("frag_main"),
+                compilation_options: compilation_options.clone(),
+                targets: &color_state_target,
+            }),
+            multiview: None,
+            cache,
+        };
+        let pipeline = device.create_render_pipeline(&pipeline_descriptor);
+
+        NormalLines {
+            pipeline,
+            lines_buffer,
+            group,
+            viewport: None,
+        }
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, length: f32, viewport: Option<Viewport>) {
+        self.viewport = viewport;
+        let lines = Lines {
+            length,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.lines_buffer, 0, bytemuck::bytes_of(&lines));
+    }
+
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        depth_view: &wgpu::TextureView,
+        sources: &[NormalSource],
+    ) {
+        if sources.is_empty() {
+            return;
+        }
+
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Normal Lines Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            ..Default::default()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
+        set_viewport(&mut render_pass, self.viewport);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(1, &self.group, &[]);
+        for (vertex_buffer, vertex_count, object_group) in sources {
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_bind_group(0, *object_group, &[]);
+            render_pass.draw(0..2, 0..*vertex_count);
+        }
+    }
+}
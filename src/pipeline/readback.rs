@@ -0,0 +1,127 @@
+use log::warn;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const DEPTH_TEXEL_SIZE: u64 = std::mem::size_of::<f32>() as u64;
+
+enum ReadbackState {
+    Idle,
+    Copied,
+    Mapping(Arc<AtomicBool>),
+}
+
+// Reads one depth texel back per frame. The copy recorded in one frame is
+// mapped after submission and only read in a later frame, so the render loop
+// never waits on the GPU.
+pub struct DepthReadback {
+    buffer: wgpu::Buffer,
+    state: ReadbackState,
+    texel: Option<(u32, u32)>,
+    depth: Option<f32>,
+    multisampled: bool,
+}
+
+impl DepthReadback {
+    pub fn new(device: &wgpu::Device) -> DepthReadback {
+        let buffer_descriptor = wgpu::BufferDescriptor {
+            label: Some("Depth Readback Buffer"),
+            size: DEPTH_TEXEL_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        };
+
+        DepthReadback {
+            buffer: device.create_buffer(&buffer_descriptor),
+            state: ReadbackState::Idle,
+            texel: None,
+            depth: None,
+            multisampled: false,
+        }
+    }
+
+    pub fn set_texel(&mut self, texel: Option<(u32, u32)>) {
+        self.texel = texel;
+        if texel.is_none() {
+            self.depth = None;
+        }
+    }
+
+    pub fn depth(&self) -> Option<f32> {
+        self.depth
+    }
+
+    pub fn copy(&mut self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        let (ReadbackState::Idle, Some((x, y))) = (&self.state, self.texel) else {
+            return;
+        };
+        // Multisampled depth cannot be copied out of the texture, which is
+        // reported once each time multisampling is turned on
+        let multisampled = texture.sample_count() > 1;
+        if multisampled && !self.multisampled {
+            warn!("depth readback is unavailable with multisampling, cursor depth is disabled");
+        }
+        self.multisampled = multisampled;
+        if multisampled || x >= texture.width() || y >= texture.height() {
+            self.depth = None;
+            return;
+        }
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::DepthOnly,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.state = ReadbackState::Copied;
+    }
+
+    // Must be called after the encoder holding the copy has been submitted
+    pub fn map(&mut self) {
+        if !matches!(self.state, ReadbackState::Copied) {
+            return;
+        }
+
+        let mapped = Arc::new(AtomicBool::new(false));
+        let callback_mapped = mapped.clone();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if result.is_ok() {
+                    callback_mapped.store(true, Ordering::Release);
+                }
+            });
+        self.state = ReadbackState::Mapping(mapped);
+    }
+
+    pub fn poll(&mut self) {
+        let ReadbackState::Mapping(mapped) = &self.state else {
+            return;
+        };
+        if !mapped.load(Ordering::Acquire) {
+            return;
+        }
+
+        let depth = {
+            let view = self.buffer.slice(..).get_mapped_range();
+            *bytemuck::from_bytes::<f32>(&view)
+        };
+        self.buffer.unmap();
+        self.depth = self.texel.map(|_| depth);
+        self.state = ReadbackState::Idle;
+    }
+}
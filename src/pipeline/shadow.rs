@@ -0,0 +1,710 @@
+use super::create_uniform_buffer;
+use super::mesh::{DirectionalLight, PointLight, Vertex};
+use crate::cache::{Cache, VecCache};
+use crate::scene::{Camera, FovAxis, Scene, perspective_transform};
+
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix};
+
+pub const POINT_SHADOW_RESOLUTION: u32 = 1024;
+pub const DIRECTIONAL_SHADOW_RESOLUTION: u32 = 2048;
+pub const MAX_CASCADES: u32 = 4;
+pub const CASCADE_COUNT: u32 = 3;
+pub const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+pub const MAX_DIRECTIONAL_SHADOWS: u32 = 4;
+pub const DIRECTIONAL_SHADOW_COUNT: u32 = 1;
+
+const CASCADE_DEPTH_PADDING: f32 = 50.0;
+
+const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Face {
+    pub view_proj: [[f32; 4]; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Cascades {
+    pub view_projs: [[[f32; 4]; 4]; (MAX_CASCADES * MAX_DIRECTIONAL_SHADOWS) as usize],
+    pub splits: [f32; MAX_CASCADES as usize],
+    pub count: u32,
+    pub _padding: [u32; 3],
+}
+
+pub struct Casters<'a> {
+    pub object_groups: &'a [wgpu::BindGroup],
+    pub vertex_buffers: &'a [wgpu::Buffer],
+    pub index_buffers: &'a [wgpu::Buffer],
+    pub index_lengths: &'a [u32],
+    pub index_formats: &'a [wgpu::IndexFormat],
+    pub visibility: &'a [bool],
+    // The model each object draws, indexing the buffers above
+    pub object_models: &'a [usize],
+}
+
+struct FaceTargets {
+    views: Vec<wgpu::TextureView>,
+    buffers: Vec<wgpu::Buffer>,
+    groups: Vec<wgpu::BindGroup>,
+}
+
+pub struct Shadows {
+    pub point: PointShadows,
+    pub directional: DirectionalShadows,
+    pub sampler: wgpu::Sampler,
+}
+
+pub struct PointShadows {
+    pipeline: wgpu::RenderPipeline,
+    face_group_layout: wgpu::BindGroupLayout,
+    view: wgpu::TextureView,
+    faces: FaceTargets,
+    resolution: u32,
+    capacity: usize,
+    count: usize,
+    outdated: bool,
+}
+
+pub struct DirectionalShadows {
+    pipeline: wgpu::RenderPipeline,
+    face_group_layout: wgpu::BindGroupLayout,
+    view: wgpu::TextureView,
+    faces: FaceTargets,
+    cascades_buffer: wgpu::Buffer,
+    resolution: u32,
+    cascade_count: u32,
+    split_lambda: f32,
+    shadow_count: u32,
+    count: usize,
+    outdated: bool,
+}
+
+impl Shadows {
+    pub fn new(
+        device: &wgpu::Device,
+        object_group_layout: &wgpu::BindGroupLayout,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Shadows {
+        let sampler_descriptor = wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        };
+
+        Shadows {
+            point: PointShadows::new(device, object_group_layout, cache),
+            directional: DirectionalShadows::new(device, object_group_layout, cache),
+            sampler: device.create_sampler(&sampler_descriptor),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &mut Scene,
+    ) -> bool {
+        let point_recreated = self.point.update(device, queue, &mut scene.point_lights);
+        let directional_recreated =
+            self.directional
+                .update(device, queue, &mut scene.directional_lights, &scene.camera);
+
+        point_recreated || directional_recreated
+    }
+
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, casters: &Casters) {
+        self.point.draw(encoder, casters);
+        self.directional.draw(encoder, casters);
+    }
+}
+
+impl PointShadows {
+    pub fn new(
+        device: &wgpu::Device,
+        object_group_layout: &wgpu::BindGroupLayout,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> PointShadows {
+        let face_group_layout = create_face_group_layout(device, "Point Shadow");
+        let pipeline = create_pipeline(
+            device,
+            object_group_layout,
+            &face_group_layout,
+            "Point Shadow",
+            cache,
+        );
+
+        let resolution = POINT_SHADOW_RESOLUTION;
+        let capacity = 1;
+        let (view, faces) = Self::create_cubes(device, &face_group_layout, resolution, capacity);
+
+        PointShadows {
+            pipeline,
+            face_group_layout,
+            view,
+            faces,
+            resolution,
+            capacity,
+            count: 0,
+            outdated: false,
+        }
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn set_resolution(&mut self, resolution: u32) {
+        if resolution != self.resolution {
+            self.resolution = resolution;
+            self.outdated = true;
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        point_lights: &mut VecCache<PointLight>,
+    ) -> bool {
+        if !point_lights.is_dirty() && !self.outdated {
+            return false;
+        }
+
+        let mut count = 0;
+        for i in 0..point_lights.len() {
+            let light = point_lights.values()[i];
+            let shadow_index = if light.casts_shadow != 0 && light.enabled != 0 {
+                count += 1;
+                count as i32 - 1
+            } else {
+                -1
+            };
+            // Only changed indices mark the lights for upload
+            if light.shadow_index != shadow_index
+                && let Some(light) = point_lights.get_mut(i)
+            {
+                light.shadow_index = shadow_index;
+            }
+        }
+
+        let recreated = self.outdated || count > self.capacity;
+        if recreated {
+            self.capacity = self.capacity.max(super::grown_capacity(count) as usize);
+            let (view, faces) = Self::create_cubes(
+                device,
+                &self.face_group_layout,
+                self.resolution,
+                self.capacity,
+            );
+            self.view = view;
+            self.faces = faces;
+            self.outdated = false;
+        }
+
+        for light in point_lights
+            .values()
+            .iter()
+            .filter(|light| light.casts_shadow != 0 && light.enabled != 0)
+        {
+            let position = cgmath::Point3::from(light.position);
+            let projection = perspective_transform(
+                light.shadow_near,
+                light.shadow_far,
+                1.0,
+                std::f32::consts::FRAC_PI_2,
+                FovAxis::Vertical,
+                false,
+            );
+            for (i, (direction, up)) in CUBE_FACES.iter().enumerate() {
+                let view = cgmath::Matrix4::look_to_lh(
+                    position,
+                    cgmath::Vector3::from(*direction),
+                    cgmath::Vector3::from(*up),
+                );
+                let face = Face {
+                    view_proj: (projection * view).into(),
+                };
+                let buffer = &self.faces.buffers[6 * light.shadow_index as usize + i];
+                queue.write_buffer(buffer, 0, bytemuck::bytes_of(&face));
+            }
+        }
+        self.count = count;
+
+        recreated
+    }
+
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, casters: &Casters) {
+        draw_faces(
+            encoder,
+            &self.pipeline,
+            &self.faces,
+            6 * self.count,
+            "Point Shadow Render Pass",
+            casters,
+        );
+    }
+
+    fn create_cubes(
+        device: &wgpu::Device,
+        face_group_layout: &wgpu::BindGroupLayout,
+        resolution: u32,
+        capacity: usize,
+    ) -> (wgpu::TextureView, FaceTargets) {
+        let layers = 6 * capacity as u32;
+        let texture = create_texture(device, "Point Shadow Texture", resolution, layers);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Point Shadow Cube Array View"),
+            dimension: Some(wgpu::TextureViewDimension::CubeArray),
+            ..Default::default()
+        });
+        let faces =
+            create_face_targets(device, face_group_layout, &texture, layers, "Point Shadow");
+
+        (view, faces)
+    }
+}
+
+impl DirectionalShadows {
+    pub fn new(
+        device: &wgpu::Device,
+        object_group_layout: &wgpu::BindGroupLayout,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> DirectionalShadows {
+        let face_group_layout = create_face_group_layout(device, "Directional Shadow");
+        let pipeline = create_pipeline(
+            device,
+            object_group_layout,
+            &face_group_layout,
+            "Directional Shadow",
+            cache,
+        );
+
+        let resolution = DIRECTIONAL_SHADOW_RESOLUTION;
+        let cascade_count = CASCADE_COUNT;
+        let shadow_count = DIRECTIONAL_SHADOW_COUNT;
+        let (view, faces) = Self::create_cascades(
+            device,
+            &face_group_layout,
+            resolution,
+            shadow_count * cascade_count,
+        );
+        let cascades_buffer = create_uniform_buffer::<Cascades>(device, None);
+
+        DirectionalShadows {
+            pipeline,
+            face_group_layout,
+            view,
+            faces,
+            cascades_buffer,
+            resolution,
+            cascade_count,
+            split_lambda: CASCADE_SPLIT_LAMBDA,
+            shadow_count,
+            count: 0,
+            outdated: false,
+        }
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn cascades_buffer(&self) -> &wgpu::Buffer {
+        &self.cascades_buffer
+    }
+
+    pub fn set_cascades(&mut self, cascade_count: u32, split_lambda: f32) {
+        let cascade_count = cascade_count.clamp(1, MAX_CASCADES);
+        if cascade_count != self.cascade_count {
+            self.cascade_count = cascade_count;
+            self.outdated = true;
+        }
+        let split_lambda = split_lambda.clamp(0.0, 1.0);
+        if split_lambda != self.split_lambda {
+            self.split_lambda = split_lambda;
+            self.outdated = true;
+        }
+    }
+
+    // Only the first shadow_count shadow casting lights get a shadow map
+    pub fn set_shadow_count(&mut self, shadow_count: u32) {
+        let shadow_count = shadow_count.clamp(1, MAX_DIRECTIONAL_SHADOWS);
+        if shadow_count != self.shadow_count {
+            self.shadow_count = shadow_count;
+            self.outdated = true;
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        directional_lights: &mut VecCache<DirectionalLight>,
+        camera: &Cache<Camera>,
+    ) -> bool {
+        if !directional_lights.is_dirty() && !camera.is_dirty() && !self.outdated {
+            return false;
+        }
+
+        let mut directions = Vec::new();
+        for i in 0..directional_lights.len() {
+            let light = directional_lights.values()[i];
+            let shadow_index = if light.casts_shadow != 0
+                && light.enabled != 0
+                && directions.len() < self.shadow_count as usize
+            {
+                directions.push(cgmath::Vector3::from(light.direction).normalize());
+                directions.len() as i32 - 1
+            } else {
+                -1
+            };
+            if light.shadow_index != shadow_index
+                && let Some(light) = directional_lights.get_mut(i)
+            {
+                light.shadow_index = shadow_index;
+            }
+        }
+
+        let recreated = self.outdated;
+        if recreated {
+            let (view, faces) = Self::create_cascades(
+                device,
+                &self.face_group_layout,
+                self.resolution,
+                self.shadow_count * self.cascade_count,
+            );
+            self.view = view;
+            self.faces = faces;
+            self.outdated = false;
+        }
+
+        self.count = directions.len();
+        let cascades = self.compute_cascades(camera, &directions);
+        for i in 0..self.count * self.cascade_count as usize {
+            let face = Face {
+                view_proj: cascades.view_projs[i],
+            };
+            queue.write_buffer(&self.faces.buffers[i], 0, bytemuck::bytes_of(&face));
+        }
+        queue.write_buffer(&self.cascades_buffer, 0, bytemuck::bytes_of(&cascades));
+
+        recreated
+    }
+
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, casters: &Casters) {
+        if self.count == 0 {
+            return;
+        }
+
+        draw_faces(
+            encoder,
+            &self.pipeline,
+            &self.faces,
+            self.count * self.cascade_count as usize,
+            "Directional Shadow Render Pass",
+            casters,
+        );
+    }
+
+    // Cascade i of shadow casting light j uses matrix and layer j * cascade_count + i
+    fn compute_cascades(&self, camera: &Camera, directions: &[cgmath::Vector3<f32>]) -> Cascades {
+        let mut cascades = Cascades {
+            view_projs: [cgmath::Matrix4::identity().into();
+                (MAX_CASCADES * MAX_DIRECTIONAL_SHADOWS) as usize],
+            splits: [camera.far; MAX_CASCADES as usize],
+            count: self.cascade_count,
+            _padding: [0, 0, 0],
+        };
+
+        let inverse_view = camera.view.invert().unwrap_or(cgmath::Matrix4::identity());
+
+        let count = self.cascade_count as f32;
+        let mut split_near = camera.near;
+        for i in 0..self.cascade_count as usize {
+            let fraction = (i + 1) as f32 / count;
+            let uniform_split = camera.near + (camera.far - camera.near) * fraction;
+            let log_split = camera.near * (camera.far / camera.near).powf(fraction);
+            let split_far =
+                self.split_lambda * log_split + (1.0 - self.split_lambda) * uniform_split;
+
+            let mut corners = Vec::new();
+            for depth in [split_near, split_far] {
+                let half_width = depth / camera.projection.x.x;
+                let half_height = depth / camera.projection.y.y;
+                for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+                    let corner = inverse_view
+                        * cgmath::Vector4::new(x * half_width, y * half_height, depth, 1.0);
+                    corners.push(cgmath::Point3::new(corner.x, corner.y, corner.z));
+                }
+            }
+
+            let center = cgmath::Point3::centroid(&corners);
+            let radius = corners
+                .iter()
+                .map(|corner| (corner - center).magnitude())
+                .fold(0.0, f32::max)
+                .ceil();
+
+            let depth_range = 2.0 * radius + CASCADE_DEPTH_PADDING;
+            let projection = cgmath::Matrix4::from_cols(
+                cgmath::Vector4::new(1.0 / radius, 0.0, 0.0, 0.0),
+                cgmath::Vector4::new(0.0, 1.0 / radius, 0.0, 0.0),
+                cgmath::Vector4::new(0.0, 0.0, 1.0 / depth_range, 0.0),
+                cgmath::Vector4::new(0.0, 0.0, 0.0, 1.0),
+            );
+            for (j, direction) in directions.iter().enumerate() {
+                let up = if direction.y.abs() > 0.99 {
+                    cgmath::Vector3::unit_z()
+                } else {
+                    cgmath::Vector3::unit_y()
+                };
+                let eye = center - direction * (radius + CASCADE_DEPTH_PADDING);
+                let view = cgmath::Matrix4::look_to_lh(eye, *direction, up);
+
+                let mut view_proj = projection * view;
+                let texels = self.resolution as f32 / 2.0;
+                let origin = view_proj * cgmath::Vector4::new(0.0, 0.0, 0.0, 1.0);
+                view_proj.w.x += (origin.x * texels).round() / texels - origin.x;
+                view_proj.w.y += (origin.y * texels).round() / texels - origin.y;
+
+                cascades.view_projs[j * self.cascade_count as usize + i] = view_proj.into();
+            }
+
+            cascades.splits[i] = split_far;
+            split_near = split_far;
+        }
+
+        cascades
+    }
+
+    fn create_cascades(
+        device: &wgpu::Device,
+        face_group_layout: &wgpu::BindGroupLayout,
+        resolution: u32,
+        cascade_count: u32,
+    ) -> (wgpu::TextureView, FaceTargets) {
+        let texture = create_texture(
+            device,
+            "Directional Shadow Texture",
+            resolution,
+            cascade_count,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Directional Shadow Array View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let faces = create_face_targets(
+            device,
+            face_group_layout,
+            &texture,
+            cascade_count,
+            "Directional Shadow",
+        );
+
+        (view, faces)
+    }
+}
+
+fn create_face_group_layout(device: &wgpu::Device, name: &str) -> wgpu::BindGroupLayout {
+    let label = format!("{} Face Bind Group Layout", name);
+    let face_group_layout_descriptor = wgpu::BindGroupLayoutDescriptor {
+        label: Some(&label),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    };
+
+    device.create_bind_group_layout(&face_group_layout_descriptor)
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    object_group_layout: &wgpu::BindGroupLayout,
+    face_group_layout: &wgpu::BindGroupLayout,
+    name: &str,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/shadow.wgsl"));
+
+    let layout_label = format!("{} Pipeline Layout", name);
+    let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
+        label: Some(&layout_label),
+        bind_group_layouts: &[object_group_layout, face_group_layout],
+        push_constant_ranges: &[],
+    };
+    let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_descriptor);
+
+    let depth_stencil_state = wgpu::DepthStencilState {
+        format: wgpu::TextureFormat::Depth32Float,
+        depth_compare: wgpu::CompareFunction::LessEqual,
+        depth_write_enabled: true,
+        stencil: wgpu::StencilState {
+            ..Default::default()
+        },
+        bias: wgpu::DepthBiasState {
+            constant: 2,
+            slope_scale: 2.0,
+            clamp: 0.0,
+        },
+    };
+
+    let compilation_options = wgpu::PipelineCompilationOptions {
+        ..Default::default()
+    };
+    let pipeline_label = format!("{} Pipeline", name);
+    let pipeline_descriptor = wgpu::RenderPipelineDescriptor {
+        label: Some(&pipeline_label),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vert_main"),
+            compilation_options: compilation_options.clone(),
+            buffers: &[Vertex::LAYOUT],
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: Some(depth_stencil_state),
+        multisample: wgpu::MultisampleState {
+            ..Default::default()
+        },
+        fragment: None,
+        multiview: None,
+        cache,
+    };
+
+    device.create_render_pipeline(&pipeline_descriptor)
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    label: &str,
+    resolution: u32,
+    layers: u32,
+) -> wgpu::Texture {
+    let texture_descriptor = wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Depth32Float,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    };
+
+    device.create_texture(&texture_descriptor)
+}
+
+fn create_face_targets(
+    device: &wgpu::Device,
+    face_group_layout: &wgpu::BindGroupLayout,
+    texture: &wgpu::Texture,
+    layers: u32,
+    name: &str,
+) -> FaceTargets {
+    let view_label = format!("{} Face View", name);
+    let group_label = format!("{} Face Bind Group", name);
+
+    let mut views = Vec::new();
+    let mut buffers = Vec::new();
+    let mut groups = Vec::new();
+    for layer in 0..layers {
+        views.push(texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(&view_label),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        }));
+
+        let face_buffer = create_uniform_buffer::<Face>(device, None);
+        let face_group_descriptor = wgpu::BindGroupDescriptor {
+            label: Some(&group_label),
+            layout: face_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(face_buffer.as_entire_buffer_binding()),
+            }],
+        };
+        groups.push(device.create_bind_group(&face_group_descriptor));
+        buffers.push(face_buffer);
+    }
+
+    FaceTargets {
+        views,
+        buffers,
+        groups,
+    }
+}
+
+fn draw_faces(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    faces: &FaceTargets,
+    count: usize,
+    label: &str,
+    casters: &Casters,
+) {
+    for face in 0..count {
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &faces.views[face],
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            ..Default::default()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(1, &faces.groups[face], &[]);
+        for (i, &model) in casters.object_models.iter().enumerate() {
+            if casters.visibility.get(i) == Some(&false)
+                || model >= casters.vertex_buffers.len()
+                || casters.index_lengths[model] == 0
+            {
+                continue;
+            }
+            render_pass.set_vertex_buffer(0, casters.vertex_buffers[model].slice(..));
+            render_pass.set_index_buffer(
+                casters.index_buffers[model].slice(..),
+                casters.index_formats[model],
+            );
+            render_pass.set_bind_group(0, casters.object_groups.get(i), &[]);
+            render_pass.draw_indexed(0..casters.index_lengths[model], 0, 0..1);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const MAX_TIMESTAMPS: u32 = 64;
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+enum TimerState {
+    Idle,
+    Recording(Vec<&'static str>),
+    Resolved(Vec<&'static str>),
+    Mapping(Vec<&'static str>, Arc<AtomicBool>),
+}
+
+// Writes a timestamp between render passes with empty compute passes, so the
+// passes themselves don't need to know about the query set. Results are read
+// back a few frames late in the same way as the depth readback.
+pub struct PassTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    period: f32,
+    state: TimerState,
+    timings: Vec<(&'static str, f32)>,
+}
+
+impl PassTimer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<PassTimer> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set_descriptor = wgpu::QuerySetDescriptor {
+            label: Some("Pass Timer Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_TIMESTAMPS,
+        };
+        let resolve_buffer_descriptor = wgpu::BufferDescriptor {
+            label: Some("Pass Timer Resolve Buffer"),
+            size: MAX_TIMESTAMPS as u64 * TIMESTAMP_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        };
+        let readback_buffer_descriptor = wgpu::BufferDescriptor {
+            label: Some("Pass Timer Readback Buffer"),
+            size: MAX_TIMESTAMPS as u64 * TIMESTAMP_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        };
+
+        Some(PassTimer {
+            query_set: device.create_query_set(&query_set_descriptor),
+            resolve_buffer: device.create_buffer(&resolve_buffer_descriptor),
+            readback_buffer: device.create_buffer(&readback_buffer_descriptor),
+            period: queue.get_timestamp_period(),
+            state: TimerState::Idle,
+            timings: Vec::new(),
+        })
+    }
+
+    // Pass durations in milliseconds from the last frame that was read back
+    pub fn timings(&self) -> &[(&'static str, f32)] {
+        &self.timings
+    }
+
+    fn write_timestamp(&self, encoder: &mut wgpu::CommandEncoder, index: u32) {
+        let compute_pass_descriptor = wgpu::ComputePassDescriptor {
+            label: Some("Pass Timer Compute Pass"),
+            timestamp_writes: Some(wgpu::ComputePassTimestampWrites {
+                query_set: &self.query_set,
+                beginning_of_pass_write_index: Some(index),
+                end_of_pass_write_index: None,
+            }),
+        };
+        encoder.begin_compute_pass(&compute_pass_descriptor);
+    }
+
+    // Frames are skipped while the previous results are still being read back
+    pub fn begin(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !matches!(self.state, TimerState::Idle) {
+            return;
+        }
+
+        self.write_timestamp(encoder, 0);
+        self.state = TimerState::Recording(Vec::new());
+    }
+
+    // Records the time taken since the previous mark under the given label
+    pub fn mark(&mut self, encoder: &mut wgpu::CommandEncoder, label: &'static str) {
+        let TimerState::Recording(labels) = &self.state else {
+            return;
+        };
+        let index = labels.len() as u32 + 1;
+        if index >= MAX_TIMESTAMPS {
+            return;
+        }
+
+        self.write_timestamp(encoder, index);
+        if let TimerState::Recording(labels) = &mut self.state {
+            labels.push(label);
+        }
+    }
+
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let TimerState::Recording(labels) = std::mem::replace(&mut self.state, TimerState::Idle)
+        else {
+            return;
+        };
+        if labels.is_empty() {
+            return;
+        }
+
+        let count = labels.len() as u32 + 1;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            count as u64 * TIMESTAMP_SIZE,
+        );
+        self.state = TimerState::Resolved(labels);
+    }
+
+    // Must be called after the encoder holding the resolve has been submitted
+    pub fn map(&mut self) {
+        let TimerState::Resolved(labels) = std::mem::replace(&mut self.state, TimerState::Idle)
+        else {
+            return;
+        };
+
+        let mapped = Arc::new(AtomicBool::new(false));
+        let callback_mapped = mapped.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if result.is_ok() {
+                    callback_mapped.store(true, Ordering::Release);
+                }
+            });
+        self.state = TimerState::Mapping(labels, mapped);
+    }
+
+    pub fn poll(&mut self) {
+        let TimerState::Mapping(labels, mapped) = &self.state else {
+            return;
+        };
+        if !mapped.load(Ordering::Acquire) {
+            return;
+        }
+
+        let timestamps = {
+            let view = self.readback_buffer.slice(..).get_mapped_range();
+            bytemuck::cast_slice::<u8, u64>(&view)[..=labels.len()].to_vec()
+        };
+        self.readback_buffer.unmap();
+        self.timings = labels
+            .iter()
+            .zip(timestamps.windows(2))
+            .map(|(label, pair)| {
+                let ticks = pair[1].wrapping_sub(pair[0]);
+                (*label, ticks as f32 * self.period / 1_000_000.0)
+            })
+            .collect();
+        self.state = TimerState::Idle;
+    }
+}
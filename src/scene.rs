@@ -1,74 +1,385 @@
+mod ply;
+mod stl;
+
 use crate::pipeline::*;
 use crate::cache::{Cache, VecCache};
 
-use cgmath::{InnerSpace, Quaternion, Rotation3, Transform};
+use cgmath::{
+    EuclideanSpace, InnerSpace, One, Quaternion, Rotation3, SquareMatrix, Transform as _,
+};
+use log::{error, info, warn};
 use std::collections::HashMap;
+use std::path::Path;
+use winit::event::MouseButton;
 use winit::keyboard::*;
 
+pub use ply::{PlyError, load_ply};
+pub use stl::{StlError, load_stl};
+
+const LIGHT_STRENGTH_RATE: f32 = 2.0;
+const FOV_ZOOM_RATE: f32 = 0.05;
+const MIN_FOV: f32 = 0.1;
+const MAX_FOV: f32 = 2.5;
+const DOLLY_RATE: f32 = 0.5;
+const MIN_FOCUS_DISTANCE: f32 = 0.25;
+const ORBIT_PAN_RATE: f32 = 0.1;
+const MAX_ORBIT_ELEVATION: f32 = 0.99;
+const SELECTION_COLOR: [f32; 4] = [1.0, 0.6, 0.0, 1.0];
+const WIREFRAME_COLOR: [f32; 4] = [0.02, 0.02, 0.02, 1.0];
+const AMBIENT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+const AMBIENT_INTENSITY: f32 = 0.03;
+const MODEL_SPACING: f32 = 3.0;
+const KEYFRAME_INTERVAL: f32 = 2.0;
+const TURNTABLE_RATE: f32 = 0.5;
+const LOD_DISTANCE: f32 = 10.0;
+const CAMERA_SPEED: f32 = 2.5;
+const CAMERA_ROT_RATE: f32 = 0.4;
+
+pub const OBJ_LOAD_OPTIONS: tobj::LoadOptions = tobj::LoadOptions {
+    triangulate: true,
+    single_index: true,
+    ignore_points: true,
+    ignore_lines: true,
+};
+
+pub const OBJ_POINT_LOAD_OPTIONS: tobj::LoadOptions = tobj::LoadOptions {
+    ignore_points: false,
+    ..OBJ_LOAD_OPTIONS
+};
+
 pub struct InitData {
     pub models: Vec<Model>,
 }
 
+#[derive(Clone)]
 pub struct Model {
     pub vertex_buffer: Vec<mesh::Vertex>,
     pub index_buffer: Vec<u32>,
+    pub index_format: wgpu::IndexFormat,
+    pub material_groups: Vec<MaterialGroup>,
+    pub textures: Vec<image::RgbaImage>,
+    pub lods: Vec<Model>,
 }
 
-#[macro_export]
-macro_rules! load_model {
-    ($name:literal) => {
-        (|| -> Result<crate::scene::Model, tobj::LoadError> {
-            let models = tobj::load_obj_buf(
-                &mut std::io::Cursor::new(include_bytes!($name)),
-                &tobj::LoadOptions {
-                    triangulate: true,
-                    single_index: true,
-                    ignore_points: true,
-                    ignore_lines: true,
-                },
-                |_| Ok((vec![], ahash::AHashMap::new())),
-            )?
-            .0;
-
-            let mut vertices = Vec::new();
-            let mut indices = Vec::new();
-            for model in models {
-                debug_assert!(model.mesh.positions.len() % 3 == 0);
-                for i in 0..model.mesh.positions.len() / 3 {
-                    let vertex = crate::pipeline::mesh::Vertex {
-                        position: [
-                            model.mesh.positions[3 * i],
-                            model.mesh.positions[3 * i + 1],
-                            model.mesh.positions[3 * i + 2],
-                        ],
-                        normal: [
-                            model.mesh.normals[3 * i],
-                            model.mesh.normals[3 * i + 1],
-                            model.mesh.normals[3 * i + 2],
-                        ],
-                        uv: [model.mesh.texcoords[2 * i], model.mesh.texcoords[2 * i + 1]],
-                    };
-                    vertices.push(vertex);
-                }
-                for index in model.mesh.indices {
-                    indices.push(index);
+// A range of the index buffer drawn with one material and, optionally, one of
+// the model's textures
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaterialGroup {
+    pub start: u32,
+    pub count: u32,
+    pub material: mesh::Material,
+    pub texture: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModelLoadOptions {
+    pub flip_uv_v: bool,
+    pub deduplicate_vertices: bool,
+    pub point_cloud: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SceneStats {
+    pub object_count: usize,
+    pub triangle_count: usize,
+    pub point_light_count: usize,
+    pub directional_light_count: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: cgmath::Point3<f32>,
+    pub max: cgmath::Point3<f32>,
+}
+
+impl Model {
+    pub fn load_obj(path: &Path, options: &ModelLoadOptions) -> Result<Model, tobj::LoadError> {
+        let load_options = if options.point_cloud {
+            &OBJ_POINT_LOAD_OPTIONS
+        } else {
+            &OBJ_LOAD_OPTIONS
+        };
+        let (models, materials) = tobj::load_obj(path, load_options)?;
+        let materials = materials.unwrap_or_else(|err| {
+            warn!("failed to load materials for {}: {}", path.display(), err);
+            Vec::new()
+        });
+
+        // Texture paths are relative to the OBJ file
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let textures = materials
+            .iter()
+            .map(|material| {
+                let texture_path = directory.join(material.diffuse_texture.as_ref()?);
+                image::open(&texture_path)
+                    .inspect_err(|err| {
+                        warn!("failed to load texture {}: {}", texture_path.display(), err)
+                    })
+                    .ok()
+                    .map(|texture| texture.into_rgba8())
+            })
+            .collect();
+        Ok(Model::from_tobj(models, &materials, textures, options))
+    }
+
+    // Textures are given per material, in the same order as materials
+    pub fn from_tobj(
+        models: Vec<tobj::Model>,
+        materials: &[tobj::Material],
+        textures: Vec<Option<image::RgbaImage>>,
+        options: &ModelLoadOptions,
+    ) -> Model {
+        let mut model_textures = Vec::new();
+        let texture_ids: Vec<Option<usize>> = textures
+            .into_iter()
+            .map(|texture| {
+                let texture = texture?;
+                model_textures.push(texture);
+                Some(model_textures.len() - 1)
+            })
+            .collect();
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut material_groups: Vec<MaterialGroup> = Vec::new();
+        for model in models {
+            let material = model
+                .mesh
+                .material_id
+                .and_then(|id| materials.get(id))
+                .map_or(mesh::Material::DEFAULT, mesh::Material::from_tobj);
+            let texture = model
+                .mesh
+                .material_id
+                .and_then(|id| texture_ids.get(id).copied().flatten());
+            let start = indices.len() as u32;
+            let count = model.mesh.indices.len() as u32;
+            match material_groups.last_mut() {
+                Some(group) if group.material == material && group.texture == texture => {
+                    group.count += count
                 }
+                _ => material_groups.push(MaterialGroup {
+                    start,
+                    count,
+                    material,
+                    texture,
+                }),
             }
 
-            Ok(crate::scene::Model {
-                vertex_buffer: vertices,
-                index_buffer: indices,
+            let mesh = model.mesh;
+            let base = vertices.len() as u32;
+            debug_assert!(mesh.positions.len() % 3 == 0);
+            for i in 0..mesh.positions.len() / 3 {
+                let normal = mesh.normals.get(3 * i..3 * i + 3).unwrap_or(&[0.0; 3]);
+                let uv = mesh.texcoords.get(2 * i..2 * i + 2).unwrap_or(&[0.0; 2]);
+                let color = mesh.vertex_color.get(3 * i..3 * i + 3).unwrap_or(&[1.0; 3]);
+                let v = if options.flip_uv_v {
+                    1.0 - uv[1]
+                } else {
+                    uv[1]
+                };
+                let vertex = mesh::Vertex {
+                    position: [
+                        mesh.positions[3 * i],
+                        mesh.positions[3 * i + 1],
+                        -mesh.positions[3 * i + 2],
+                    ],
+                    normal: [normal[0], normal[1], -normal[2]],
+                    uv: [uv[0], v],
+                    color: [color[0], color[1], color[2]],
+                };
+                vertices.push(vertex);
+            }
+            indices.extend(mesh.indices.iter().map(|index| base + index));
+        }
+
+        // Point clouds keep only their vertices, which are drawn as sprites
+        if options.point_cloud {
+            indices.clear();
+            material_groups.clear();
+        }
+
+        let mut model = Model {
+            index_format: Model::index_format_for(vertices.len()),
+            vertex_buffer: vertices,
+            index_buffer: indices,
+            material_groups,
+            textures: model_textures,
+            lods: Vec::new(),
+        };
+        if options.deduplicate_vertices {
+            model.deduplicate_vertices();
+        }
+        model
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_buffer.len()
+    }
+
+    // Point clouds have no indices, so they count no triangles
+    pub fn triangle_count(&self) -> usize {
+        self.index_buffer.len() / 3
+    }
+
+    pub fn is_point_cloud(&self) -> bool {
+        self.index_buffer.is_empty() && !self.vertex_buffer.is_empty()
+    }
+
+    // Vertices are keyed on their bit patterns, so only exact duplicates are merged
+    pub fn deduplicate_vertices(&mut self) {
+        let mut unique = ahash::AHashMap::new();
+        let mut vertices = Vec::new();
+        let remap: Vec<u32> = self
+            .vertex_buffer
+            .iter()
+            .map(|vertex| {
+                let key: [u32; 11] = bytemuck::cast(*vertex);
+                *unique.entry(key).or_insert_with(|| {
+                    vertices.push(*vertex);
+                    vertices.len() as u32 - 1
+                })
             })
-        })()
+            .collect();
+
+        for index in &mut self.index_buffer {
+            *index = remap[*index as usize];
+        }
+        self.vertex_buffer = vertices;
+        self.index_format = Model::index_format_for(self.vertex_buffer.len());
+    }
+
+    // Area weighted face normals, summed at each vertex. Loading flips the z axis,
+    // which mirrors the winding, so the edges are crossed in reverse order.
+    pub fn generate_normals(&mut self) {
+        let mut normals = vec![cgmath::Vector3::new(0.0, 0.0, 0.0); self.vertex_buffer.len()];
+        for triangle in self.index_buffer.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2]
+                .map(|i| cgmath::Vector3::from(self.vertex_buffer[triangle[i] as usize].position));
+            let normal = (c - a).cross(b - a);
+            for &index in triangle {
+                normals[index as usize] += normal;
+            }
+        }
+
+        for (vertex, normal) in self.vertex_buffer.iter_mut().zip(normals) {
+            if normal.magnitude2() > 0.0 {
+                vertex.normal = normal.normalize().into();
+            }
+        }
+    }
+
+    // 16-bit indices reach vertex 65535, so they cover meshes of up to 65536 vertices
+    pub fn index_format_for(vertex_count: usize) -> wgpu::IndexFormat {
+        if vertex_count <= u16::MAX as usize + 1 {
+            wgpu::IndexFormat::Uint16
+        } else {
+            wgpu::IndexFormat::Uint32
+        }
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        let mut min = cgmath::Point3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = cgmath::Point3::new(f32::MIN, f32::MIN, f32::MIN);
+        for vertex in &self.vertex_buffer {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(vertex.position[axis]);
+                max[axis] = max[axis].max(vertex.position[axis]);
+            }
+        }
+
+        if self.vertex_buffer.is_empty() {
+            let origin = cgmath::Point3::new(0.0, 0.0, 0.0);
+            return Bounds {
+                min: origin,
+                max: origin,
+            };
+        }
+        Bounds { min, max }
+    }
+}
+
+impl Bounds {
+    pub fn intersect_ray(
+        &self,
+        origin: cgmath::Point3<f32>,
+        direction: cgmath::Vector3<f32>,
+    ) -> Option<f32> {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::MAX;
+        for axis in 0..3 {
+            if direction[axis].abs() < f32::EPSILON {
+                if origin[axis] < self.min[axis] || origin[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let t0 = (self.min[axis] - origin[axis]) / direction[axis];
+            let t1 = (self.max[axis] - origin[axis]) / direction[axis];
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+            if t_min > t_max {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+
+    // The axis-aligned box around this box's corners after the transform
+    pub fn transformed(&self, matrix: cgmath::Matrix4<f32>) -> Bounds {
+        let mut min = cgmath::Point3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = cgmath::Point3::new(f32::MIN, f32::MIN, f32::MIN);
+        for corner in 0..8 {
+            let mut point = self.min;
+            for axis in 0..3 {
+                if corner & (1 << axis) != 0 {
+                    point[axis] = self.max[axis];
+                }
+            }
+            let point = matrix.transform_point(point);
+            for axis in 0..3 {
+                min[axis] = min[axis].min(point[axis]);
+                max[axis] = max[axis].max(point[axis]);
+            }
+        }
+
+        Bounds { min, max }
+    }
+}
+
+#[macro_export]
+macro_rules! load_model {
+    ($name:literal) => {
+        load_model!($name, &$crate::scene::ModelLoadOptions::default())
+    };
+    ($name:literal, $options:expr) => {
+        tobj::load_obj_buf(
+            &mut std::io::Cursor::new(include_bytes!($name)),
+            &$crate::scene::OBJ_LOAD_OPTIONS,
+            |_| Ok((vec![], ahash::AHashMap::new())),
+        )
+        .map(|(models, _)| $crate::scene::Model::from_tobj(models, &[], Vec::new(), $options))
     };
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FovAxis {
+    #[default]
+    Vertical,
+    Horizontal,
+}
+
+#[derive(Clone)]
 pub struct Camera {
     pub mesh_camera: mesh::Camera,
     pub view: cgmath::Matrix4<f32>,
     pub projection: cgmath::Matrix4<f32>,
     pub position: cgmath::Point3<f32>,
     pub fov: f32,
+    pub fov_axis: FovAxis,
+    pub reverse_z: bool,
     pub aspect: f32,
     pub near: f32,
     pub far: f32,
@@ -84,8 +395,6 @@ impl Camera {
         far: f32,
         direction: cgmath::Vector3<f32>,
         position: cgmath::Point3<f32>,
-        speed: f32,
-        rot_rate: f32,
     ) -> Camera {
         let target = cgmath::Point3::new(
             position.x + direction.x,
@@ -93,23 +402,23 @@ impl Camera {
             position.z + direction.z,
         );
         let view = cgmath::Matrix4::look_at_lh(position, target, cgmath::Vector3::unit_y());
-        let projection = perspective_transform(near, far, aspect, fov);
-        return Camera {
-            mesh_camera: mesh::Camera {
-                position: position.into(),
-                _padding: 0.0,
-                view_proj: (projection * view).into(),
-            },
-            view: view,
-            projection: projection,
-            position: position,
-            fov: fov,
-            aspect: aspect,
-            near: near,
-            far: far,
-            speed: speed,
-            rot_rate: rot_rate,
-        };
+        let fov_axis = FovAxis::default();
+        let reverse_z = false;
+        let projection = perspective_transform(near, far, aspect, fov, fov_axis, reverse_z);
+        Camera {
+            mesh_camera: mesh::Camera::from_view_proj(position, projection * view, near, far),
+            view,
+            projection,
+            position,
+            fov,
+            fov_axis,
+            reverse_z,
+            aspect,
+            near,
+            far,
+            speed: CAMERA_SPEED,
+            rot_rate: CAMERA_ROT_RATE,
+        }
     }
 
     pub fn update(
@@ -121,13 +430,10 @@ impl Camera {
         speed: f32,
         rot_rate: f32,
     ) {
-        self.projection = perspective_transform(near, far, aspect, fov);
-        let mesh_camera = mesh::Camera {
-            position: self.position.into(),
-            _padding: 0.0,
-            view_proj: (self.projection * self.view).into(),
-        };
-        self.mesh_camera = mesh_camera;
+        self.projection =
+            perspective_transform(near, far, aspect, fov, self.fov_axis, self.reverse_z);
+        self.mesh_camera =
+            mesh::Camera::from_view_proj(self.position, self.projection * self.view, near, far);
         self.near = near;
         self.far = far;
         self.fov = fov;
@@ -135,23 +441,338 @@ impl Camera {
         self.speed = speed;
         self.rot_rate = rot_rate;
     }
+
+    pub fn set_fov_axis(&mut self, fov_axis: FovAxis) {
+        self.fov_axis = fov_axis;
+        self.refresh_projection();
+    }
+
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+        self.refresh_projection();
+    }
+
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        self.reverse_z = reverse_z;
+        self.refresh_projection();
+    }
+
+    pub fn screen_ray(
+        &self,
+        cursor: (f32, f32),
+        viewport: (u32, u32),
+    ) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+        let (near_z, far_z) = if self.reverse_z {
+            (1.0, 0.0)
+        } else {
+            (0.0, 1.0)
+        };
+        let origin = self.unproject(cursor, viewport, near_z);
+        let direction = (self.unproject(cursor, viewport, far_z) - origin).normalize();
+
+        (origin, direction)
+    }
+
+    pub fn unproject(
+        &self,
+        cursor: (f32, f32),
+        viewport: (u32, u32),
+        depth: f32,
+    ) -> cgmath::Point3<f32> {
+        let view_proj = cgmath::Matrix4::from(self.mesh_camera.view_proj);
+        let inverse = view_proj.invert().unwrap_or(cgmath::Matrix4::identity());
+        let x = 2.0 * cursor.0 / viewport.0.max(1) as f32 - 1.0;
+        let y = 1.0 - 2.0 * cursor.1 / viewport.1.max(1) as f32;
+        let point = inverse * cgmath::Vector4::new(x, y, depth, 1.0);
+        cgmath::Point3::new(point.x / point.w, point.y / point.w, point.z / point.w)
+    }
+
+    pub fn view_position(&self) -> cgmath::Point3<f32> {
+        self.view
+            .invert()
+            .map(|inverse_view| inverse_view.transform_point(cgmath::Point3::new(0.0, 0.0, 0.0)))
+            .unwrap_or(self.position)
+    }
+
+    pub fn view_matrix(&self) -> cgmath::Matrix4<f32> {
+        self.view
+    }
+
+    pub fn set_view_matrix(&mut self, view: cgmath::Matrix4<f32>) {
+        self.view = view;
+        self.position = self.view_position();
+        self.mesh_camera = mesh::Camera::from_view_proj(
+            self.position,
+            self.projection * self.view,
+            self.near,
+            self.far,
+        );
+    }
+
+    // Moves the camera along its view direction until a sphere around the bounds
+    // fits the narrower field of view. The orientation is left as it is.
+    pub fn frame_bounds(&mut self, bounds: Bounds) {
+        let center = bounds.min.midpoint(bounds.max);
+        let radius = (bounds.max - bounds.min).magnitude() / 2.0;
+        let tan_half_fov = f32::tan(self.fov / 2.0);
+        let (tan_x, tan_y) = match self.fov_axis {
+            FovAxis::Vertical => (tan_half_fov * self.aspect, tan_half_fov),
+            FovAxis::Horizontal => (tan_half_fov, tan_half_fov / self.aspect),
+        };
+        let half_fov = tan_x.min(tan_y).atan();
+        let distance = (radius / half_fov.sin()).max(self.near + radius);
+
+        let Some(inverse_view) = self.view.invert() else {
+            return;
+        };
+        let forward = inverse_view
+            .transform_vector(cgmath::Vector3::unit_z())
+            .normalize();
+        let position = center - forward * distance;
+        let view = self.view * cgmath::Matrix4::from_translation(self.view_position() - position);
+        self.set_view_matrix(view);
+    }
+
+    fn refresh_projection(&mut self) {
+        let (fov, aspect, near, far, speed, rot_rate) = (
+            self.fov,
+            self.aspect,
+            self.near,
+            self.far,
+            self.speed,
+            self.rot_rate,
+        );
+        self.update(fov, aspect, near, far, speed, rot_rate);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugMode {
+    Shaded = 0,
+    Normals = 1,
+    Depth = 2,
+    Uvs = 3,
+    Metallic = 4,
+    Tangents = 5,
+    Bitangents = 6,
+    Cascades = 7,
+    ClipPlanes = 8,
+    Unlit = 9,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SceneCommand {
+    SetObjectTransform(usize, cgmath::Matrix4<f32>),
+    SetPointLight(usize, mesh::PointLight),
+    SetDirectionalLight(usize, mesh::DirectionalLight),
+    SetObjectVisible(usize, bool),
+    SetObjectParent(usize, Option<usize>),
+    SetObjectLayer(usize, mesh::Layer),
+    AddObject(usize, Transform, f32),
+    RemoveObject(usize),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    #[default]
+    FreeFly,
+    Orbit,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZoomMode {
+    #[default]
+    Fov,
+    Dolly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: cgmath::Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: cgmath::Vector3<f32>,
+}
+
+impl Transform {
+    pub fn from_translation(translation: cgmath::Vector3<f32>) -> Transform {
+        Transform {
+            translation,
+            rotation: Quaternion::one(),
+            scale: cgmath::Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // Shear cannot be represented and is lost, a mirrored basis becomes a negative x scale
+    pub fn from_matrix(matrix: cgmath::Matrix4<f32>) -> Transform {
+        let mut scale = cgmath::Vector3::new(
+            matrix.x.truncate().magnitude(),
+            matrix.y.truncate().magnitude(),
+            matrix.z.truncate().magnitude(),
+        );
+        if matrix.determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+
+        let rotation = if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0 {
+            Quaternion::one()
+        } else {
+            let basis = cgmath::Matrix3::from_cols(
+                matrix.x.truncate() / scale.x,
+                matrix.y.truncate() / scale.y,
+                matrix.z.truncate() / scale.z,
+            );
+            Quaternion::from(basis).normalize()
+        };
+
+        Transform {
+            translation: matrix.w.truncate(),
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn to_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.translation)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    fn decompose_objects(objects: &[mesh::Object]) -> Vec<Transform> {
+        objects
+            .iter()
+            .map(|object| Transform::from_matrix(object.model.into()))
+            .collect()
+    }
+}
+
+// Keys currently held, plus the keys pressed since the last update so a tap
+// that is released within the same frame still registers
+#[derive(Clone, Debug, Default)]
+pub struct KeyState {
+    held: HashMap<PhysicalKey, bool>,
+    pressed: Vec<PhysicalKey>,
+}
+
+impl KeyState {
+    pub fn press(&mut self, key: PhysicalKey, repeat: bool) {
+        self.held.insert(key, true);
+        if !repeat {
+            self.pressed.push(key);
+        }
+    }
+
+    pub fn release(&mut self, key: PhysicalKey) {
+        self.held.insert(key, false);
+    }
+
+    pub fn is_held(&self, code: KeyCode) -> bool {
+        self.held
+            .get(&PhysicalKey::Code(code))
+            .is_some_and(|held| *held)
+    }
+
+    pub fn just_pressed(&self, code: KeyCode) -> bool {
+        self.pressed.contains(&PhysicalKey::Code(code))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraKeyframe {
+    pub position: cgmath::Point3<f32>,
+    pub orientation: Quaternion<f32>,
+}
+
+impl CameraKeyframe {
+    pub fn from_view(view: cgmath::Matrix4<f32>) -> CameraKeyframe {
+        let rotation = Scene::extract_rotation(&view);
+        let position = view
+            .invert()
+            .map(|inverse_view| inverse_view.transform_point(cgmath::Point3::new(0.0, 0.0, 0.0)))
+            .unwrap_or(cgmath::Point3::new(0.0, 0.0, 0.0));
+
+        CameraKeyframe {
+            position,
+            orientation: Quaternion::from(rotation).normalize(),
+        }
+    }
+
+    pub fn view(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from(self.orientation)
+            * cgmath::Matrix4::from_translation(cgmath::Point3::new(0.0, 0.0, 0.0) - self.position)
+    }
+
+    pub fn interpolate(&self, other: &CameraKeyframe, t: f32) -> CameraKeyframe {
+        CameraKeyframe {
+            position: self.position + (other.position - self.position) * t,
+            orientation: self.orientation.slerp(other.orientation, t),
+        }
+    }
 }
 
 pub struct Scene {
     pub objects: VecCache<mesh::Object>,
+    pub transforms: Vec<Transform>,
+    pub parents: Vec<Option<usize>>,
+    hierarchy_dirty: bool,
     pub point_lights: VecCache<mesh::PointLight>,
     pub directional_lights: VecCache<mesh::DirectionalLight>,
     pub camera: Cache<Camera>,
+    pub cameras: Vec<Camera>,
+    pub globals: Cache<mesh::Globals>,
+    pub model_bounds: Vec<Bounds>,
+    pub model_triangle_counts: Vec<usize>,
+    pub lod_distances: Vec<Vec<f32>>,
+    pub max_lights: usize,
+    pub visibility: Vec<bool>,
+    pub layers: Vec<mesh::Layer>,
+    pub object_models: Vec<usize>,
+    pub selected: Option<usize>,
+    pub camera_mode: CameraMode,
+    pub zoom_mode: ZoomMode,
+    pub focus: cgmath::Point3<f32>,
+    pub world_aligned_movement: bool,
+    pub keyframes: Vec<(f32, CameraKeyframe)>,
+    pub loop_keyframes: bool,
+    pub playback: Option<f32>,
+    pub turntable: bool,
+    pub turntable_rate: f32,
+    pub show_bounds: bool,
+    pub show_wireframe: bool,
+    pub show_normals: bool,
+    pub normal_length: f32,
+    pub front_face: wgpu::FrontFace,
 }
 
-pub fn perspective_transform(near: f32, far: f32, aspect: f32, fov: f32) -> cgmath::Matrix4<f32> {
+fn remove_sparse<T>(values: &mut Vec<T>, index: usize) {
+    if index < values.len() {
+        values.remove(index);
+    }
+}
+
+pub fn perspective_transform(
+    near: f32,
+    far: f32,
+    aspect: f32,
+    fov: f32,
+    fov_axis: FovAxis,
+    reverse_z: bool,
+) -> cgmath::Matrix4<f32> {
     let c = 1.0 / f32::tan(fov / 2.0);
-    return cgmath::Matrix4::from_cols(
-        cgmath::Vector4::new(c / aspect, 0.0, 0.0, 0.0),
-        cgmath::Vector4::new(0.0, c, 0.0, 0.0),
-        cgmath::Vector4::new(0.0, 0.0, far / (far - near), 1.0),
-        cgmath::Vector4::new(0.0, 0.0, -(far * near) / (far - near), 0.0),
-    );
+    let (x_scale, y_scale) = match fov_axis {
+        FovAxis::Vertical => (c / aspect, c),
+        FovAxis::Horizontal => (c, c * aspect),
+    };
+    let (z_scale, z_offset) = if reverse_z {
+        (-near / (far - near), (far * near) / (far - near))
+    } else {
+        (far / (far - near), -(far * near) / (far - near))
+    };
+    cgmath::Matrix4::from_cols(
+        cgmath::Vector4::new(x_scale, 0.0, 0.0, 0.0),
+        cgmath::Vector4::new(0.0, y_scale, 0.0, 0.0),
+        cgmath::Vector4::new(0.0, 0.0, z_scale, 1.0),
+        cgmath::Vector4::new(0.0, 0.0, z_offset, 0.0),
+    )
 }
 
 impl Scene {
@@ -159,60 +780,38 @@ impl Scene {
         let direction =
             cgmath::Vector3::new(-camera_position.x, -camera_position.y, -camera_position.z)
                 .normalize();
-        let camera = Camera::new(
-            0.75,
-            aspect,
-            0.1,
-            100.0,
-            direction,
-            camera_position,
-            2.5,
-            0.4,
-        );
+        let camera = Camera::new(0.75, aspect, 0.1, 100.0, direction, camera_position);
 
-        return Scene {
-            objects: VecCache::new(vec![
-                mesh::Object {
-                    model: (cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, 1.0, 0.))
-                        * cgmath::Matrix4::from_cols(
-                            cgmath::Vector4::new(1.0, 0.0, 0.0, 0.0),
-                            cgmath::Vector4::new(0.0, 1.0, 0.0, 0.0),
-                            cgmath::Vector4::new(0.0, 0.0, -1.0, 0.0),
-                            cgmath::Vector4::new(0.0, 0.0, 0.0, 1.0),
-                        ))
-                    .into(),
-                    metallic: 0.5,
-                    _padding: [0.0, 0.0, 0.0],
-                },
-                mesh::Object {
-                    model: (cgmath::Matrix4::from_translation(cgmath::Vector3::new(1.0, 1.0, 2.))
-                        * cgmath::Matrix4::from_cols(
-                            cgmath::Vector4::new(1.0, 0.0, 0.0, 0.0),
-                            cgmath::Vector4::new(0.0, 1.0, 0.0, 0.0),
-                            cgmath::Vector4::new(0.0, 0.0, -1.0, 0.0),
-                            cgmath::Vector4::new(0.0, 0.0, 0.0, 1.0),
-                        ))
-                    .into(),
-                    metallic: 0.8,
-                    _padding: [0.0, 0.0, 0.0],
-                },
-                mesh::Object {
-                    model: (cgmath::Matrix4::from_scale(100.0) * cgmath::Matrix4::from_cols(
-                            cgmath::Vector4::new(1.0, 0.0, 0.0, 0.0),
-                            cgmath::Vector4::new(0.0, 1.0, 0.0, 0.0),
-                            cgmath::Vector4::new(0.0, 0.0, -1.0, 0.0),
-                            cgmath::Vector4::new(0.0, 0.0, 0.0, 1.0)))
-                    .into(),
-                    metallic: 0.0,
-                    _padding: [0.0, 0.0, 0.0],
-                }
-            ]),
+        let objects = vec![
+            mesh::Object::from_transform(
+                cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, 1.0, 0.)),
+                0.5,
+            ),
+            mesh::Object::from_transform(
+                cgmath::Matrix4::from_translation(cgmath::Vector3::new(1.0, 1.0, 2.)),
+                0.8,
+            ),
+            mesh::Object::from_transform(cgmath::Matrix4::from_scale(100.0), 0.0),
+        ];
+        let transforms = Transform::decompose_objects(&objects);
+
+        Scene {
+            objects: VecCache::new(objects),
+            transforms,
+            parents: Vec::new(),
+            hierarchy_dirty: false,
             point_lights: VecCache::new(vec![
                 mesh::PointLight {
                     position: [0.0, 2.0, -2.0],
                     color: [1.0, 1.0, 1.0],
                     strength: 0.0,
-                    _padding0: 0.0,
+                    radius: mesh::PointLight::radius_for_strength(0.0),
+                    casts_shadow: 0,
+                    shadow_index: -1,
+                    shadow_near: 0.1,
+                    shadow_far: 25.0,
+                    enabled: 1,
+                    _padding: [0, 0, 0],
                 },
             ]),
             directional_lights: VecCache::new(vec![
@@ -223,68 +822,892 @@ impl Scene {
                     strength: 5.0,
                     _padding0: 0.0,
                     _padding1: 0.0,
+                    casts_shadow: 0,
+                    shadow_index: -1,
+                    enabled: 1,
+                    _padding2: 0,
                 }
             ]),
             camera: Cache::new(camera),
+            cameras: Vec::new(),
+            globals: Cache::new(mesh::Globals {
+                debug_mode: DebugMode::Shaded as u32,
+                _padding: [0, 0, 0],
+                selection_color: SELECTION_COLOR,
+                wireframe_color: WIREFRAME_COLOR,
+                ambient_color: AMBIENT_COLOR,
+                ambient_intensity: AMBIENT_INTENSITY,
+            }),
+            model_bounds: Vec::new(),
+            model_triangle_counts: Vec::new(),
+            lod_distances: Vec::new(),
+            max_lights: mesh::MAX_LIGHTS,
+            visibility: Vec::new(),
+            layers: Vec::new(),
+            object_models: Vec::new(),
+            selected: None,
+            camera_mode: CameraMode::default(),
+            zoom_mode: ZoomMode::default(),
+            focus: cgmath::Point3::new(0.0, 0.0, 0.0),
+            world_aligned_movement: false,
+            keyframes: Vec::new(),
+            loop_keyframes: true,
+            playback: None,
+            turntable: false,
+            turntable_rate: TURNTABLE_RATE,
+            show_bounds: false,
+            show_wireframe: false,
+            show_normals: false,
+            normal_length: normals::NORMAL_LENGTH,
+            front_face: wgpu::FrontFace::Ccw,
+        }
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.objects.mark_dirty();
+        self.point_lights.mark_dirty();
+        self.directional_lights.mark_dirty();
+        self.camera.mark_dirty();
+        self.globals.mark_dirty();
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.objects.is_dirty()
+            || self.point_lights.is_dirty()
+            || self.directional_lights.is_dirty()
+            || self.camera.is_dirty()
+            || self.globals.is_dirty()
+    }
+
+    pub fn apply(&mut self, command: SceneCommand) {
+        let applied = match command {
+            SceneCommand::SetObjectTransform(index, model) => (index < self.objects.len())
+                .then(|| self.set_transform(index, Transform::from_matrix(model))),
+            SceneCommand::SetPointLight(index, light) => self
+                .point_lights
+                .get_mut(index)
+                .map(|point_light| *point_light = light),
+            SceneCommand::SetDirectionalLight(index, light) => self
+                .directional_lights
+                .get_mut(index)
+                .map(|directional_light| *directional_light = light),
+            SceneCommand::SetObjectVisible(index, visible) => {
+                (index < self.objects.len()).then(|| self.set_visible(index, visible))
+            }
+            SceneCommand::SetObjectParent(index, parent) => {
+                (index < self.objects.len()).then(|| self.set_parent(index, parent))
+            }
+            SceneCommand::SetObjectLayer(index, layer) => {
+                (index < self.objects.len()).then(|| self.set_layer(index, layer))
+            }
+            SceneCommand::AddObject(model, transform, metallic) => {
+                let loaded = model < self.model_bounds.len();
+                loaded
+                    .then(|| self.add_object(model, transform, metallic))
+                    .map(|_| ())
+            }
+            SceneCommand::RemoveObject(index) => self.remove_object(index).map(|_| ()),
         };
+
+        if applied.is_none() {
+            warn!(
+                "ignoring scene command with an out of range index: {:?}",
+                command
+            );
+        }
     }
 
-    fn check_key(kmap: &HashMap<PhysicalKey, bool>, code: KeyCode) -> bool {
-        return kmap
-            .get(&PhysicalKey::Code(code))
-            .is_some_and(|pressed| pressed.clone());
+    pub fn lay_out_objects(&mut self, count: usize) {
+        let offset = (count.max(1) - 1) as f32 * MODEL_SPACING / 2.0;
+        let objects = (0..count)
+            .map(|i| {
+                let translation = cgmath::Vector3::new(i as f32 * MODEL_SPACING - offset, 0.0, 0.0);
+                mesh::Object::from_transform(cgmath::Matrix4::from_translation(translation), 0.5)
+            })
+            .collect();
+        self.set_objects(objects);
+    }
+
+    pub fn set_objects(&mut self, objects: Vec<mesh::Object>) {
+        self.transforms = Transform::decompose_objects(&objects);
+        self.objects = VecCache::new(objects);
+        self.parents.clear();
+        self.visibility.clear();
+        self.layers.clear();
+        self.object_models.clear();
+        self.selected = None;
+    }
+
+    // Objects draw the model with their own index unless given another one
+    pub fn object_model(&self, index: usize) -> usize {
+        self.object_models.get(index).copied().unwrap_or(index)
+    }
+
+    // Adding and removing objects would shift the models of objects relying on
+    // their index, so every object's model is written out first
+    fn fill_object_models(&mut self) {
+        self.object_models = (0..self.objects.len())
+            .map(|i| self.object_model(i))
+            .collect();
+    }
+
+    // Returns the new object's index. Its model doesn't have to be loaded yet,
+    // the object is drawn once it is.
+    pub fn add_object(&mut self, model: usize, transform: Transform, metallic: f32) -> usize {
+        self.fill_object_models();
+        let index = self.objects.len();
+        let object = mesh::Object::from_transform(transform.to_matrix(), metallic);
+        self.objects.push(object);
+        self.transforms.truncate(index);
+        self.transforms.push(transform);
+        self.object_models.push(model);
+        index
+    }
+
+    // Later objects move down an index. Children of the removed object are
+    // detached and keep their place in the world.
+    pub fn remove_object(&mut self, index: usize) -> Option<mesh::Object> {
+        if index >= self.objects.len() {
+            return None;
+        }
+
+        self.fill_object_models();
+        for child in 0..self.objects.len() {
+            if self.parent(child) == Some(index) {
+                self.parents[child] = None;
+                let world = cgmath::Matrix4::from(self.objects.values()[child].model);
+                self.set_transform(child, Transform::from_matrix(world));
+            }
+        }
+        for parent in self.parents.iter_mut().flatten() {
+            if *parent > index {
+                *parent -= 1;
+            }
+        }
+
+        remove_sparse(&mut self.transforms, index);
+        remove_sparse(&mut self.parents, index);
+        remove_sparse(&mut self.visibility, index);
+        remove_sparse(&mut self.layers, index);
+        remove_sparse(&mut self.object_models, index);
+        remove_sparse(&mut self.lod_distances, index);
+        self.selected = match self.selected {
+            Some(selected) if selected == index => None,
+            Some(selected) if selected > index => Some(selected - 1),
+            selected => selected,
+        };
+        self.hierarchy_dirty = true;
+        Some(self.objects.remove(index))
+    }
+
+    pub fn set_transform(&mut self, index: usize, transform: Transform) {
+        if let Some(existing) = self.transforms.get_mut(index) {
+            *existing = transform;
+            self.hierarchy_dirty = true;
+        }
+    }
+
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        self.parents.get(index).copied().flatten()
+    }
+
+    pub fn set_parent(&mut self, index: usize, parent: Option<usize>) {
+        if self.parents.len() <= index {
+            self.parents.resize(index + 1, None);
+        }
+        self.parents[index] = parent;
+        self.hierarchy_dirty = true;
+    }
+
+    // Objects hold world matrices, resolved from the local transforms parents first
+    fn update_hierarchy(&mut self) {
+        let count = self.transforms.len().min(self.objects.len());
+        let mut worlds: Vec<Option<cgmath::Matrix4<f32>>> = vec![None; count];
+        for i in 0..count {
+            let mut chain = Vec::new();
+            let mut current = Some(i);
+            while let Some(index) = current
+                && worlds[index].is_none()
+            {
+                if chain.contains(&index) {
+                    let child = *chain.last().unwrap();
+                    error!(
+                        "object {} is part of a parent cycle, detaching it from object {}",
+                        child, index
+                    );
+                    self.parents[child] = None;
+                    current = None;
+                    break;
+                }
+                chain.push(index);
+                current = self.parent(index).filter(|parent| *parent < count);
+            }
+
+            let mut parent_world = current
+                .and_then(|index| worlds[index])
+                .unwrap_or(cgmath::Matrix4::identity());
+            for index in chain.into_iter().rev() {
+                parent_world = parent_world * self.transforms[index].to_matrix();
+                worlds[index] = Some(parent_world);
+            }
+        }
+
+        for (object, world) in self.objects.iter_mut().zip(worlds) {
+            if let Some(world) = world {
+                object.model = world.into();
+            }
+        }
+        self.hierarchy_dirty = false;
+    }
+
+    pub fn is_visible(&self, index: usize) -> bool {
+        self.visibility.get(index).copied().unwrap_or(true)
+    }
+
+    // Fragments whose texture alpha is below the cutoff are discarded, 0 disables it
+    pub fn set_alpha_cutoff(&mut self, index: usize, alpha_cutoff: f32) {
+        if let Some(object) = self.objects.get_mut(index) {
+            object.alpha_cutoff = alpha_cutoff;
+        }
+    }
+
+    // Disabled lights are skipped by light culling, shading and shadows
+    pub fn set_point_light_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(light) = self.point_lights.get_mut(index) {
+            light.enabled = enabled as u32;
+        }
+    }
+
+    pub fn set_directional_light_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(light) = self.directional_lights.get_mut(index) {
+            light.enabled = enabled as u32;
+        }
+    }
+
+    pub fn toggle_point_light(&mut self, index: usize) {
+        if let Some(light) = self.point_lights.values().get(index) {
+            self.set_point_light_enabled(index, light.enabled == 0);
+        }
+    }
+
+    pub fn toggle_directional_light(&mut self, index: usize) {
+        if let Some(light) = self.directional_lights.values().get(index) {
+            self.set_directional_light_enabled(index, light.enabled == 0);
+        }
+    }
+
+    pub fn set_visible(&mut self, index: usize, visible: bool) {
+        if self.visibility.len() <= index {
+            self.visibility.resize(index + 1, true);
+        }
+        self.visibility[index] = visible;
+    }
+
+    pub fn layer(&self, index: usize) -> mesh::Layer {
+        self.layers.get(index).copied().unwrap_or_default()
+    }
+
+    pub fn set_layer(&mut self, index: usize, layer: mesh::Layer) {
+        if self.layers.len() <= index {
+            self.layers.resize(index + 1, mesh::Layer::default());
+        }
+        self.layers[index] = layer;
+    }
+
+    // Triangle counts are stored per model when models are loaded
+    pub fn stats(&self) -> SceneStats {
+        SceneStats {
+            object_count: self.objects.len(),
+            triangle_count: (0..self.objects.len())
+                .filter_map(|i| self.model_triangle_counts.get(self.object_model(i)))
+                .sum(),
+            point_light_count: self.point_lights.len(),
+            directional_light_count: self.directional_lights.len(),
+        }
+    }
+
+    pub fn pick(
+        &self,
+        origin: cgmath::Point3<f32>,
+        direction: cgmath::Vector3<f32>,
+    ) -> Option<usize> {
+        let mut nearest: Option<(usize, f32)> = None;
+        for (i, object) in self.objects.values().iter().enumerate() {
+            if !self.is_visible(i) {
+                continue;
+            }
+            let Some(bounds) = self.model_bounds.get(self.object_model(i)) else {
+                continue;
+            };
+            let Some(inverse) = cgmath::Matrix4::from(object.model).invert() else {
+                continue;
+            };
+
+            let local_origin = inverse.transform_point(origin);
+            let local_direction = inverse.transform_vector(direction);
+            if let Some(t) = bounds.intersect_ray(local_origin, local_direction)
+                && nearest.is_none_or(|(_, nearest_t)| t < nearest_t)
+            {
+                nearest = Some((i, t));
+            }
+        }
+
+        nearest.map(|(i, _)| i)
+    }
+
+    pub fn set_camera_mode(&mut self, camera_mode: CameraMode) {
+        self.camera_mode = camera_mode;
+        let position = self.camera.view_position();
+        if camera_mode == CameraMode::Orbit && position != self.focus {
+            let view = cgmath::Matrix4::look_at_lh(position, self.focus, cgmath::Vector3::unit_y());
+            self.camera.set_view_matrix(view);
+        }
+    }
+
+    pub fn set_focus(&mut self, focus: cgmath::Point3<f32>) {
+        info!("focus: ({:.2}, {:.2}, {:.2})", focus.x, focus.y, focus.z);
+        self.focus = focus;
+        self.set_camera_mode(self.camera_mode);
+    }
+
+    // Frames the selected object and orbits around its center from then on
+    pub fn frame_selection(&mut self) {
+        let Some(selected) = self.selected else {
+            return;
+        };
+        let (Some(object), Some(bounds)) = (
+            self.objects.values().get(selected),
+            self.model_bounds.get(self.object_model(selected)),
+        ) else {
+            return;
+        };
+
+        let bounds = bounds.transformed(cgmath::Matrix4::from(object.model));
+        self.camera.frame_bounds(bounds);
+        self.focus = bounds.min.midpoint(bounds.max);
+    }
+
+    // Objects without their own thresholds step down a level every LOD_DISTANCE.
+    // Level 0 is the object's own model and level n its nth entry in Model::lods.
+    pub fn lod_level(&self, object: usize, lod_count: usize) -> usize {
+        let Some(object_value) = self.objects.values().get(object) else {
+            return 0;
+        };
+        if lod_count == 0 {
+            return 0;
+        }
+
+        let [x, y, z, _] = object_value.model[3];
+        let distance = (cgmath::Point3::new(x, y, z) - self.camera.view_position()).magnitude();
+        let level = match self.lod_distances.get(object) {
+            Some(distances) if !distances.is_empty() => distances
+                .iter()
+                .take_while(|threshold| distance >= **threshold)
+                .count(),
+            _ => (distance / LOD_DISTANCE) as usize,
+        };
+        level.min(lod_count)
+    }
+
+    // Checked by the mesh pipeline for each kind of light
+    pub fn set_max_lights(&mut self, max_lights: usize) {
+        self.max_lights = max_lights;
+    }
+
+    pub fn set_lod_distances(&mut self, object: usize, distances: Vec<f32>) {
+        if self.lod_distances.len() <= object {
+            self.lod_distances.resize(object + 1, Vec::new());
+        }
+        self.lod_distances[object] = distances;
+    }
+
+    // The extra cameras start as copies of the main one, which keeps
+    // following input while the others stay where they were left.
+    pub fn set_split_screen(&mut self, split_screen: bool) {
+        self.cameras.clear();
+        if split_screen {
+            self.cameras.push(self.camera.value().clone());
+        }
+        info!("split screen: {}", split_screen);
+        self.mark_dirty();
+    }
+
+    pub fn rotate_orbit(&mut self, angle: f32) {
+        let yaw = Quaternion::from_axis_angle(cgmath::Vector3::unit_y(), cgmath::Rad(angle));
+        let position = self.focus + yaw * (self.camera.view_position() - self.focus);
+        let view = cgmath::Matrix4::look_at_lh(position, self.focus, cgmath::Vector3::unit_y());
+        self.camera.set_view_matrix(view);
+    }
+
+    // Rotates the camera about the world Y axis through the origin, keeping its radius and height
+    pub fn rotate_turntable(&mut self, angle: f32) {
+        let yaw = cgmath::Matrix4::from_angle_y(cgmath::Rad(-angle));
+        let view = self.camera.view * yaw;
+        self.camera.set_view_matrix(view);
+    }
+
+    pub fn add_keyframe(&mut self, time: f32) {
+        let keyframe = CameraKeyframe::from_view(self.camera.view);
+        let index = self.keyframes.partition_point(|(t, _)| *t <= time);
+        self.keyframes.insert(index, (time, keyframe));
+    }
+
+    pub fn toggle_playback(&mut self) {
+        self.playback = match self.playback {
+            None if !self.keyframes.is_empty() => Some(0.0),
+            _ => None,
+        };
+    }
+
+    pub fn keyframe_at(&self, time: f32) -> Option<CameraKeyframe> {
+        let index = self.keyframes.partition_point(|(t, _)| *t <= time);
+        let previous = index.checked_sub(1).and_then(|i| self.keyframes.get(i));
+        match (previous, self.keyframes.get(index)) {
+            (Some((start, from)), Some((end, to))) => {
+                Some(from.interpolate(to, (time - start) / (end - start)))
+            }
+            (Some((_, keyframe)), None) | (None, Some((_, keyframe))) => Some(*keyframe),
+            (None, None) => None,
+        }
+    }
+
+    pub fn set_selection_color(&mut self, color: [f32; 4]) {
+        self.globals.selection_color = color;
+    }
+
+    pub fn set_wireframe_color(&mut self, color: [f32; 4]) {
+        self.globals.wireframe_color = color;
+    }
+
+    // Length of the lines drawn along vertex normals, in world units
+    pub fn set_normal_length(&mut self, length: f32) {
+        self.normal_length = length;
+    }
+
+    // Added to every lit surface, so an intensity of 0 leaves only direct lighting
+    pub fn set_ambient(&mut self, color: [f32; 3], intensity: f32) {
+        self.globals.ambient_color = color;
+        self.globals.ambient_intensity = intensity;
+    }
+
+    pub fn set_debug_mode(&mut self, debug_mode: DebugMode) {
+        self.globals.debug_mode = debug_mode as u32;
+    }
+
+    pub fn on_key_pressed(&mut self, code: KeyCode) {
+        let debug_mode = match code {
+            KeyCode::Digit1 => DebugMode::Normals,
+            KeyCode::Digit2 => DebugMode::Depth,
+            KeyCode::Digit3 => DebugMode::Uvs,
+            KeyCode::Digit4 => DebugMode::Metallic,
+            KeyCode::Digit5 => DebugMode::Tangents,
+            KeyCode::Digit6 => DebugMode::Bitangents,
+            KeyCode::Digit7 => DebugMode::Cascades,
+            KeyCode::Digit8 => DebugMode::ClipPlanes,
+            KeyCode::Digit9 => DebugMode::Unlit,
+            KeyCode::KeyZ => {
+                self.zoom_mode = match self.zoom_mode {
+                    ZoomMode::Fov => ZoomMode::Dolly,
+                    ZoomMode::Dolly => ZoomMode::Fov,
+                };
+                return;
+            }
+            KeyCode::KeyK => {
+                let time = self
+                    .keyframes
+                    .last()
+                    .map_or(0.0, |(time, _)| time + KEYFRAME_INTERVAL);
+                self.add_keyframe(time);
+                return;
+            }
+            KeyCode::KeyP => {
+                self.toggle_playback();
+                return;
+            }
+            KeyCode::KeyN => {
+                info!(
+                    "camera near: {}, far: {}, projection: {:?}",
+                    self.camera.near, self.camera.far, self.camera.projection
+                );
+                return;
+            }
+            KeyCode::KeyB => {
+                self.show_bounds = !self.show_bounds;
+                self.mark_dirty();
+                return;
+            }
+            KeyCode::KeyL => {
+                self.show_wireframe = !self.show_wireframe;
+                self.mark_dirty();
+                return;
+            }
+            KeyCode::KeyC => {
+                self.frame_selection();
+                return;
+            }
+            KeyCode::KeyH => {
+                self.show_normals = !self.show_normals;
+                self.mark_dirty();
+                return;
+            }
+            KeyCode::KeyY => {
+                if let Some(selected) = self.selected {
+                    let layer = self.layer(selected).next();
+                    info!("object {} layer: {:?}", selected, layer);
+                    self.set_layer(selected, layer);
+                    self.mark_dirty();
+                }
+                return;
+            }
+            KeyCode::KeyM => {
+                self.set_split_screen(self.cameras.is_empty());
+                return;
+            }
+            KeyCode::KeyR => {
+                self.front_face = match self.front_face {
+                    wgpu::FrontFace::Ccw => wgpu::FrontFace::Cw,
+                    wgpu::FrontFace::Cw => wgpu::FrontFace::Ccw,
+                };
+                info!("front face: {:?}", self.front_face);
+                self.mark_dirty();
+                return;
+            }
+            KeyCode::KeyT => {
+                self.turntable = !self.turntable;
+                return;
+            }
+            KeyCode::KeyO => {
+                let camera_mode = match self.camera_mode {
+                    CameraMode::FreeFly => CameraMode::Orbit,
+                    CameraMode::Orbit => CameraMode::FreeFly,
+                };
+                self.set_camera_mode(camera_mode);
+                return;
+            }
+            _ => return,
+        };
+
+        if self.globals.debug_mode == debug_mode as u32 {
+            self.set_debug_mode(DebugMode::Shaded);
+        } else {
+            self.set_debug_mode(debug_mode);
+        }
+    }
+
+    fn check_key(keys: &KeyState, code: KeyCode) -> bool {
+        keys.is_held(code) || keys.just_pressed(code)
+    }
+
+    fn check_button(buttons: &HashMap<MouseButton, bool>, button: MouseButton) -> bool {
+        buttons.get(&button).is_some_and(|pressed| *pressed)
     }
 
     fn extract_rotation<S: Copy>(matrix: &cgmath::Matrix4<S>) -> cgmath::Matrix3<S> {
-        return cgmath::Matrix3::new(
+        cgmath::Matrix3::new(
             matrix.x.x, matrix.x.y, matrix.x.z, matrix.y.x, matrix.y.y, matrix.y.z, matrix.z.x,
             matrix.z.y, matrix.z.z,
-        );
+        )
     }
 
     pub fn update(
         &mut self,
-        kmap: &HashMap<PhysicalKey, bool>,
+        keys: &mut KeyState,
+        buttons: &HashMap<MouseButton, bool>,
         mouse_movements: &mut Vec<(f32, f32)>,
+        scroll: &mut f32,
         delta: f32,
     ) {
-        let forward_pressed = Self::check_key(&kmap, KeyCode::KeyW);
-        let backwards_pressed = Self::check_key(&kmap, KeyCode::KeyS);
-        let right_pressed = Self::check_key(&kmap, KeyCode::KeyD);
-        let left_pressed = Self::check_key(&kmap, KeyCode::KeyA);
+        for key in &keys.pressed {
+            if let PhysicalKey::Code(code) = *key {
+                self.on_key_pressed(code);
+            }
+        }
+
+        if self.hierarchy_dirty {
+            self.update_hierarchy();
+        }
+
+        let playing = self.advance_playback(delta);
+        let turning = !playing && self.turntable;
+        if turning {
+            self.rotate_turntable(self.turntable_rate * delta);
+        }
+        let camera_locked = playing || turning;
+        let forward_pressed = Self::check_key(keys, KeyCode::KeyW);
+        let backwards_pressed = Self::check_key(keys, KeyCode::KeyS);
+        let right_pressed = Self::check_key(keys, KeyCode::KeyD);
+        let left_pressed = Self::check_key(keys, KeyCode::KeyA);
 
         let forward_axis = ((forward_pressed as i32) - (backwards_pressed as i32)) as f32;
         let side_axis = ((right_pressed as i32) - (left_pressed as i32)) as f32;
 
-        let displacement = delta
-            * self.camera.value.speed
+        let mut displacement = delta
+            * self.camera.speed
             * ((-forward_axis * cgmath::Vector3::unit_z())
                 + (-side_axis * cgmath::Vector3::unit_x()));
+        let mut world_displacement = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        if self.world_aligned_movement
+            && let Some(inverse_view) = self.camera.view.invert()
+        {
+            let flatten = |axis: cgmath::Vector3<f32>| {
+                let axis = inverse_view.transform_vector(axis);
+                let axis = cgmath::Vector3::new(axis.x, 0.0, axis.z);
+                if axis.magnitude2() > f32::EPSILON {
+                    axis.normalize()
+                } else {
+                    axis
+                }
+            };
+            world_displacement = delta
+                * self.camera.speed
+                * (forward_axis * flatten(cgmath::Vector3::unit_z())
+                    + side_axis * flatten(cgmath::Vector3::unit_x()));
+            displacement = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        }
         let mut total_movement = (0.0, 0.0);
         for movement in mouse_movements.iter() {
             total_movement = (movement.0 + total_movement.0, movement.1 + total_movement.1);
         }
         mouse_movements.clear();
-        let local_rotation = Quaternion::from_axis_angle(
-            cgmath::Vector3::unit_x(),
-            cgmath::Rad(-self.camera.value.rot_rate * total_movement.1 * delta),
-        );
+        let camera_moved = forward_axis != 0.0 || side_axis != 0.0 || total_movement != (0.0, 0.0);
+        if camera_moved && !camera_locked && self.camera_mode == CameraMode::FreeFly {
+            let local_rotation = Quaternion::from_axis_angle(
+                cgmath::Vector3::unit_x(),
+                cgmath::Rad(-self.camera.rot_rate * total_movement.1 * delta),
+            );
 
-        let world_y = <cgmath::Matrix3<f32> as Transform<cgmath::Point3<f32>>>::transform_vector(
-            &Self::extract_rotation(&self.camera.value.view),
-            cgmath::Vector3::unit_y(),
-        );
-        let world_y = world_y.normalize();
-        let global_rotation = Quaternion::from_axis_angle(
-            world_y,
-            cgmath::Rad(-self.camera.value.rot_rate * total_movement.0 * delta),
+            let world_y =
+                <cgmath::Matrix3<f32> as cgmath::Transform<cgmath::Point3<f32>>>::transform_vector(
+                    &Self::extract_rotation(&self.camera.view),
+                    cgmath::Vector3::unit_y(),
+                );
+            let world_y = world_y.normalize();
+            let global_rotation = Quaternion::from_axis_angle(
+                world_y,
+                cgmath::Rad(-self.camera.rot_rate * total_movement.0 * delta),
+            );
+
+            self.camera.view = cgmath::Matrix4::from_translation(displacement)
+                * Into::<cgmath::Matrix4<f32>>::into(local_rotation)
+                * Into::<cgmath::Matrix4<f32>>::into(global_rotation)
+                * self.camera.view
+                * cgmath::Matrix4::from_translation(-world_displacement);
+            self.camera.mesh_camera = mesh::Camera::from_view_proj(
+                self.camera.position,
+                self.camera.projection * self.camera.view,
+                self.camera.near,
+                self.camera.far,
+            );
+        }
+        if total_movement != (0.0, 0.0) && !camera_locked && self.camera_mode == CameraMode::Orbit {
+            self.orbit(buttons, total_movement, delta);
+        }
+
+        let scroll = std::mem::take(scroll);
+        if scroll != 0.0 && !camera_locked {
+            self.zoom(scroll);
+        }
+
+        let point_up_pressed = Self::check_key(keys, KeyCode::ArrowUp);
+        let point_down_pressed = Self::check_key(keys, KeyCode::ArrowDown);
+        let directional_up_pressed = Self::check_key(keys, KeyCode::ArrowRight);
+        let directional_down_pressed = Self::check_key(keys, KeyCode::ArrowLeft);
+        keys.pressed.clear();
+
+        let point_strength_axis = ((point_up_pressed as i32) - (point_down_pressed as i32)) as f32;
+        let directional_strength_axis =
+            ((directional_up_pressed as i32) - (directional_down_pressed as i32)) as f32;
+        if point_strength_axis != 0.0
+            && let Some(light) = self.point_lights.get_mut(0)
+        {
+            light.strength = Self::adjust_strength(light.strength, point_strength_axis, delta);
+            light.radius = mesh::PointLight::radius_for_strength(light.strength);
+        }
+        if directional_strength_axis != 0.0
+            && let Some(light) = self.directional_lights.get_mut(0)
+        {
+            light.strength =
+                Self::adjust_strength(light.strength, directional_strength_axis, delta);
+        }
+    }
+
+    fn advance_playback(&mut self, delta: f32) -> bool {
+        let (Some(time), Some((end, _))) = (self.playback, self.keyframes.last()) else {
+            return false;
+        };
+
+        let mut time = time + delta;
+        if time > *end {
+            if self.loop_keyframes && *end > 0.0 {
+                time %= end;
+            } else {
+                time = *end;
+                self.playback = None;
+            }
+        }
+        if self.playback.is_some() {
+            self.playback = Some(time);
+        }
+        if let Some(keyframe) = self.keyframe_at(time) {
+            self.camera.set_view_matrix(keyframe.view());
+        }
+        true
+    }
+
+    fn orbit(&mut self, buttons: &HashMap<MouseButton, bool>, movement: (f32, f32), delta: f32) {
+        let Some(inverse_view) = self.camera.view.invert() else {
+            return;
+        };
+        let right = inverse_view
+            .transform_vector(cgmath::Vector3::unit_x())
+            .normalize();
+        let up = inverse_view
+            .transform_vector(cgmath::Vector3::unit_y())
+            .normalize();
+        let mut position = inverse_view.transform_point(cgmath::Point3::new(0.0, 0.0, 0.0));
+
+        if Self::check_button(buttons, MouseButton::Middle) {
+            let distance = (position - self.focus).magnitude();
+            let pan = (-right * movement.0 + up * movement.1) * ORBIT_PAN_RATE * distance * delta;
+            self.focus += pan;
+            position += pan;
+        } else if Self::check_button(buttons, MouseButton::Right) {
+            let yaw = Quaternion::from_axis_angle(
+                cgmath::Vector3::unit_y(),
+                cgmath::Rad(self.camera.rot_rate * movement.0 * delta),
+            );
+            let pitch = Quaternion::from_axis_angle(
+                right,
+                cgmath::Rad(self.camera.rot_rate * movement.1 * delta),
+            );
+            let offset = position - self.focus;
+            let orbited = yaw * pitch * offset;
+            position = if orbited.normalize().y.abs() < MAX_ORBIT_ELEVATION {
+                self.focus + orbited
+            } else {
+                self.focus + yaw * offset
+            };
+        } else {
+            return;
+        }
+
+        let view = cgmath::Matrix4::look_at_lh(position, self.focus, cgmath::Vector3::unit_y());
+        self.camera.set_view_matrix(view);
+    }
+
+    fn zoom(&mut self, amount: f32) {
+        let zoom_mode = match self.camera_mode {
+            CameraMode::FreeFly => self.zoom_mode,
+            CameraMode::Orbit => ZoomMode::Dolly,
+        };
+        match zoom_mode {
+            ZoomMode::Fov => {
+                let fov = (self.camera.fov - amount * FOV_ZOOM_RATE).clamp(MIN_FOV, MAX_FOV);
+                let (aspect, near, far, speed, rot_rate) = (
+                    self.camera.aspect,
+                    self.camera.near,
+                    self.camera.far,
+                    self.camera.speed,
+                    self.camera.rot_rate,
+                );
+                self.camera.update(fov, aspect, near, far, speed, rot_rate);
+            }
+            ZoomMode::Dolly => {
+                let mut distance = amount * DOLLY_RATE;
+                let focus = self.camera.view.transform_point(self.focus);
+                if focus.z > 0.0 {
+                    distance = distance.min(focus.z - MIN_FOCUS_DISTANCE);
+                }
+
+                let view = cgmath::Matrix4::from_translation(-distance * cgmath::Vector3::unit_z())
+                    * self.camera.view;
+                self.camera.set_view_matrix(view);
+            }
+        }
+    }
+
+    fn adjust_strength(strength: f32, axis: f32, delta: f32) -> f32 {
+        (strength + axis * LIGHT_STRENGTH_RATE * delta).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn to_ndc(view_proj: cgmath::Matrix4<f32>, point: cgmath::Point3<f32>) -> cgmath::Vector3<f32> {
+        let clip = view_proj * point.to_homogeneous();
+        clip.truncate() / clip.w
+    }
+
+    fn assert_close(actual: cgmath::Vector3<f32>, expected: cgmath::Vector3<f32>) {
+        assert!(
+            (actual - expected).magnitude() < EPSILON,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
         );
+    }
+
+    // A camera at the origin looking down +z with a 90 degree vertical fov and
+    // a 2:1 aspect, so the frustum edges at distance d sit at x = 2d, y = d
+    #[test]
+    fn projects_to_ndc() {
+        let (near, far) = (0.5, 50.0);
+        let (origin, forward) = (cgmath::Point3::origin(), cgmath::Vector3::unit_z());
+        let mut camera = Camera::new(FRAC_PI_2, 2.0, near, far, forward, origin);
+        let view = camera.view_matrix();
+
+        for reverse_z in [false, true] {
+            camera.set_reverse_z(reverse_z);
+            let projection =
+                perspective_transform(near, far, 2.0, FRAC_PI_2, FovAxis::Vertical, reverse_z);
+            let view_proj = projection * view;
+            let (near_z, far_z) = if reverse_z { (1.0, 0.0) } else { (0.0, 1.0) };
+
+            let ndc = to_ndc(view_proj, cgmath::Point3::new(0.0, 0.0, near));
+            assert_close(ndc, cgmath::Vector3::new(0.0, 0.0, near_z));
+            let ndc = to_ndc(view_proj, cgmath::Point3::new(2.0 * far, far, far));
+            assert_close(ndc, cgmath::Vector3::new(1.0, 1.0, far_z));
+            let ndc = to_ndc(view_proj, cgmath::Point3::new(-4.0, -2.0, 2.0));
+            assert_close(ndc, cgmath::Vector3::new(-1.0, -1.0, ndc.z));
+            assert!(ndc.z > 0.0 && ndc.z < 1.0);
 
-        self.camera.value.view = cgmath::Matrix4::from_translation(displacement)
-            * Into::<cgmath::Matrix4<f32>>::into(local_rotation)
-            * Into::<cgmath::Matrix4<f32>>::into(global_rotation)
-            * self.camera.value.view;
-        self.camera.value.mesh_camera.view_proj = (self.camera.value.projection * self.camera.value.view).into();
-        self.camera.dirty = true;
+            // The camera's own matrices match the free function
+            let camera_ndc = to_ndc(
+                cgmath::Matrix4::from(camera.mesh_camera.view_proj),
+                cgmath::Point3::new(-4.0, -2.0, 2.0),
+            );
+            assert_close(camera_ndc, ndc);
+        }
+    }
+
+    // Projects a world point to pixel coordinates and casts the ray back
+    // through that pixel, which has to pass through the original point
+    #[test]
+    fn screen_ray_round_trips() {
+        let viewport = (640, 480);
+        let target = cgmath::Point3::new(0.5, 1.0, 0.3);
+        let mut camera = Scene::new(640.0 / 480.0, cgmath::Point3::new(1.0, 2.0, -5.0))
+            .camera
+            .clone();
+
+        for reverse_z in [false, true] {
+            camera.set_reverse_z(reverse_z);
+            let ndc = to_ndc(cgmath::Matrix4::from(camera.mesh_camera.view_proj), target);
+            let cursor = (
+                (ndc.x + 1.0) / 2.0 * viewport.0 as f32,
+                (1.0 - ndc.y) / 2.0 * viewport.1 as f32,
+            );
+
+            let (origin, direction) = camera.screen_ray(cursor, viewport);
+            let offset = target - origin;
+            let along = offset.dot(direction);
+            assert!(along > 0.0);
+            assert!((offset - direction * along).magnitude() < 1e-3);
+        }
     }
 }
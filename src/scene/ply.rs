@@ -0,0 +1,464 @@
+use super::Model;
+use crate::pipeline::mesh;
+
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PlyError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("invalid header: {0}")]
+    Header(String),
+    #[error("invalid value {0:?}")]
+    Value(String),
+    #[error("unexpected end of file")]
+    UnexpectedEof,
+    #[error("vertex index {0} is out of range")]
+    IndexOutOfRange(i64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Property {
+    Scalar(Scalar),
+    List(Scalar, Scalar),
+}
+
+struct Element<'a> {
+    name: &'a str,
+    count: usize,
+    properties: Vec<(&'a str, Property)>,
+}
+
+// Vertex properties kept from the file, in the order of the slots they are read into
+const VERTEX_PROPERTIES: [&[&str]; 11] = [
+    &["x"],
+    &["y"],
+    &["z"],
+    &["nx"],
+    &["ny"],
+    &["nz"],
+    &["u", "s", "texture_u"],
+    &["v", "t", "texture_v"],
+    &["red", "diffuse_red"],
+    &["green", "diffuse_green"],
+    &["blue", "diffuse_blue"],
+];
+const COLOR_SLOTS: std::ops::Range<usize> = 8..11;
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    // Integer colors span their type's range, float colors are already in 0..1
+    fn color_range(self) -> f64 {
+        match self {
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            _ => 1.0,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+struct Body<'a> {
+    data: &'a [u8],
+    encoding: Encoding,
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, PlyError> {
+        match self.encoding {
+            Encoding::Ascii => self.read_ascii(),
+            Encoding::LittleEndian | Encoding::BigEndian => self.read_binary(scalar),
+        }
+    }
+
+    fn read_ascii(&mut self) -> Result<f64, PlyError> {
+        let start = self
+            .data
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .ok_or(PlyError::UnexpectedEof)?;
+        let data = &self.data[start..];
+        let end = data
+            .iter()
+            .position(u8::is_ascii_whitespace)
+            .unwrap_or(data.len());
+        let token = String::from_utf8_lossy(&data[..end]);
+        self.data = &data[end..];
+        token
+            .parse()
+            .map_err(|_| PlyError::Value(token.into_owned()))
+    }
+
+    fn read_binary(&mut self, scalar: Scalar) -> Result<f64, PlyError> {
+        let size = scalar.size();
+        if self.data.len() < size {
+            return Err(PlyError::UnexpectedEof);
+        }
+        let (value, rest) = self.data.split_at(size);
+        self.data = rest;
+
+        let mut bytes = [0; 8];
+        bytes[..size].copy_from_slice(value);
+        if self.encoding == Encoding::BigEndian {
+            bytes[..size].reverse();
+        }
+        let [b0, b1, b2, b3, ..] = bytes;
+        Ok(match scalar {
+            Scalar::I8 => b0 as i8 as f64,
+            Scalar::U8 => b0 as f64,
+            Scalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::F64 => f64::from_le_bytes(bytes),
+        })
+    }
+
+    fn read_list(&mut self, count: Scalar, item: Scalar) -> Result<Vec<f64>, PlyError> {
+        let count = self.read(count)?;
+        if count < 0.0 {
+            return Err(PlyError::Value(count.to_string()));
+        }
+        (0..count as usize).map(|_| self.read(item)).collect()
+    }
+}
+
+// Indices past the last vertex are only caught once every element is read
+fn face_index(value: f64) -> Result<u32, PlyError> {
+    if value.fract() != 0.0 {
+        return Err(PlyError::Value(value.to_string()));
+    }
+    u32::try_from(value as i64).map_err(|_| PlyError::IndexOutOfRange(value as i64))
+}
+
+fn parse_header(data: &[u8]) -> Result<(Encoding, Vec<Element<'_>>, &[u8]), PlyError> {
+    let mut rest = data;
+    let mut lines = Vec::new();
+    loop {
+        let end = rest
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(|| PlyError::Header(String::from("missing end_header")))?;
+        let line = std::str::from_utf8(&rest[..end])
+            .map_err(|_| PlyError::Header(String::from("header is not valid text")))?
+            .trim();
+        rest = &rest[end + 1..];
+        if line == "end_header" {
+            break;
+        }
+        lines.push(line);
+    }
+
+    if lines.first() != Some(&"ply") {
+        return Err(PlyError::Header(String::from("missing ply magic")));
+    }
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in &lines[1..] {
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        match words[..] {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", format, _] => {
+                encoding = Some(match format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::LittleEndian,
+                    "binary_big_endian" => Encoding::BigEndian,
+                    _ => return Err(PlyError::Header(format!("unknown format {format}"))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name,
+                count: count
+                    .parse()
+                    .map_err(|_| PlyError::Header(format!("invalid count for {name}")))?,
+                properties: Vec::new(),
+            }),
+            ["property", ..] => {
+                let property = match words[1..] {
+                    ["list", count, item, name] => Scalar::parse(count)
+                        .zip(Scalar::parse(item))
+                        .map(|(count, item)| (name, Property::List(count, item))),
+                    [scalar, name] => {
+                        Scalar::parse(scalar).map(|scalar| (name, Property::Scalar(scalar)))
+                    }
+                    _ => None,
+                };
+                let property =
+                    property.ok_or_else(|| PlyError::Header(format!("invalid line {line:?}")))?;
+                elements
+                    .last_mut()
+                    .ok_or_else(|| PlyError::Header(String::from("property before element")))?
+                    .properties
+                    .push(property);
+            }
+            _ => return Err(PlyError::Header(format!("invalid line {line:?}"))),
+        }
+    }
+
+    let encoding = encoding.ok_or_else(|| PlyError::Header(String::from("missing format")))?;
+    Ok((encoding, elements, rest))
+}
+
+// Faces are triangulated as fans and elements other than vertices and faces are
+// skipped. Vertices without a color are white, and alpha is dropped.
+pub fn load_ply(path: &Path) -> Result<Model, PlyError> {
+    parse_ply(&std::fs::read(path)?)
+}
+
+fn parse_ply(data: &[u8]) -> Result<Model, PlyError> {
+    let (encoding, elements, data) = parse_header(data)?;
+    let mut body = Body { data, encoding };
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut has_normals = false;
+    for element in &elements {
+        let slots: Vec<Option<usize>> = element
+            .properties
+            .iter()
+            .map(|(name, _)| {
+                VERTEX_PROPERTIES
+                    .iter()
+                    .position(|names| names.contains(name))
+            })
+            .collect();
+        if element.name == "vertex" {
+            has_normals = (3..6).all(|slot| slots.contains(&Some(slot)));
+        }
+
+        for _ in 0..element.count {
+            let mut values = [0.0; 11];
+            values[COLOR_SLOTS].fill(1.0);
+            for ((name, property), slot) in element.properties.iter().zip(&slots) {
+                match *property {
+                    Property::Scalar(scalar) => {
+                        let value = body.read(scalar)?;
+                        if let Some(slot) = *slot {
+                            values[slot] = if COLOR_SLOTS.contains(&slot) {
+                                (value / scalar.color_range()) as f32
+                            } else {
+                                value as f32
+                            };
+                        }
+                    }
+                    Property::List(count, item) => {
+                        let list = body.read_list(count, item)?;
+                        let is_face = element.name == "face"
+                            && (*name == "vertex_indices" || *name == "vertex_index");
+                        if is_face && list.len() >= 3 {
+                            let list = list
+                                .into_iter()
+                                .map(face_index)
+                                .collect::<Result<Vec<_>, _>>()?;
+                            for i in 1..list.len() - 1 {
+                                indices.extend([list[0], list[i], list[i + 1]]);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if element.name == "vertex" {
+                vertices.push(mesh::Vertex {
+                    position: [values[0], values[1], -values[2]],
+                    normal: [values[3], values[4], -values[5]],
+                    uv: [values[6], values[7]],
+                    color: [values[8], values[9], values[10]],
+                });
+            }
+        }
+    }
+
+    if let Some(&index) = indices
+        .iter()
+        .find(|&&index| index as usize >= vertices.len())
+    {
+        return Err(PlyError::IndexOutOfRange(index as i64));
+    }
+
+    let mut model = Model {
+        index_format: Model::index_format_for(vertices.len()),
+        vertex_buffer: vertices,
+        index_buffer: indices,
+        material_groups: Vec::new(),
+        textures: Vec::new(),
+        lods: Vec::new(),
+    };
+    if !has_normals {
+        model.generate_normals();
+    }
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+
+    fn header(format: &str, faces: usize) -> String {
+        format!(
+            "ply\nformat {format} 1.0\ncomment test\nelement vertex 4\nproperty float x\n\
+             property float y\nproperty float z\nelement face {faces}\n\
+             property list uchar int vertex_indices\nend_header\n"
+        )
+    }
+
+    // Writes the quad as one four sided face, byte swapped for big endian
+    fn binary_quad(
+        format: &str,
+        to_bytes: fn(f32) -> [u8; 4],
+        index: fn(i32) -> [u8; 4],
+    ) -> Vec<u8> {
+        let mut data = header(format, 1).into_bytes();
+        for position in QUAD {
+            data.extend(position.into_iter().flat_map(to_bytes));
+        }
+        data.push(4);
+        data.extend([0, 1, 2, 3].into_iter().flat_map(index));
+        data
+    }
+
+    fn positions(model: &Model) -> Vec<[f32; 3]> {
+        model
+            .vertex_buffer
+            .iter()
+            .map(|vertex| vertex.position)
+            .collect()
+    }
+
+    #[test]
+    fn parses_ascii() {
+        let data = header("ascii", 2) + "0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 1 2\n3 0 2 3\n";
+        let model = parse_ply(data.as_bytes()).unwrap();
+        assert_eq!(positions(&model), QUAD.map(|[x, y, z]| [x, y, -z]));
+        assert_eq!(model.index_buffer, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(model.index_format, wgpu::IndexFormat::Uint16);
+    }
+
+    #[test]
+    fn parses_binary_little_endian() {
+        let data = binary_quad("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        let model = parse_ply(&data).unwrap();
+        assert_eq!(positions(&model), QUAD.map(|[x, y, z]| [x, y, -z]));
+        assert_eq!(model.index_buffer, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn parses_binary_big_endian() {
+        let data = binary_quad("binary_big_endian", f32::to_be_bytes, i32::to_be_bytes);
+        let model = parse_ply(&data).unwrap();
+        assert_eq!(positions(&model), QUAD.map(|[x, y, z]| [x, y, -z]));
+        assert_eq!(model.index_buffer, [0, 1, 2, 0, 2, 3]);
+    }
+
+    // Quads and larger polygons are split into fans around their first vertex
+    #[test]
+    fn triangulates_polygons() {
+        let data = header("ascii", 1) + "0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 3 0 1 2\n";
+        let model = parse_ply(data.as_bytes()).unwrap();
+        assert_eq!(model.index_buffer, [3, 0, 1, 3, 1, 2]);
+        // Generated normals face -z, towards the viewer after the z flip
+        assert!(
+            model
+                .vertex_buffer
+                .iter()
+                .all(|vertex| vertex.normal == [0.0, 0.0, -1.0])
+        );
+    }
+
+    #[test]
+    fn keeps_vertex_colors() {
+        let data = "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\n\
+                    property float z\nproperty uchar red\nproperty uchar green\n\
+                    property uchar blue\nproperty uchar alpha\nend_header\n\
+                    0 0 0 255 0 51 128\n1 0 0 0 255 0 255\n";
+        let model = parse_ply(data.as_bytes()).unwrap();
+        let colors: Vec<_> = model
+            .vertex_buffer
+            .iter()
+            .map(|vertex| vertex.color)
+            .collect();
+        assert_eq!(colors, [[1.0, 0.0, 0.2], [0.0, 1.0, 0.0]]);
+
+        // Files without colors keep the material's color as is
+        let data = header("ascii", 0) + "0 0 0\n1 0 0\n1 1 0\n0 1 0\n";
+        let model = parse_ply(data.as_bytes()).unwrap();
+        assert!(
+            model
+                .vertex_buffer
+                .iter()
+                .all(|vertex| vertex.color == [1.0; 3])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_indices() {
+        let face = |indices: &str| header("ascii", 1) + "0 0 0\n1 0 0\n1 1 0\n0 1 0\n" + indices;
+        assert!(matches!(
+            parse_ply(face("3 0 -1 2\n").as_bytes()),
+            Err(PlyError::IndexOutOfRange(-1))
+        ));
+        assert!(matches!(
+            parse_ply(face("3 0 1 4\n").as_bytes()),
+            Err(PlyError::IndexOutOfRange(4))
+        ));
+        assert!(matches!(
+            parse_ply(face("3 0 1 1.5\n").as_bytes()),
+            Err(PlyError::Value(_))
+        ));
+        assert!(matches!(
+            parse_ply(face("3 0 1\n").as_bytes()),
+            Err(PlyError::UnexpectedEof)
+        ));
+    }
+}
@@ -0,0 +1,180 @@
+use super::Model;
+use crate::pipeline::mesh;
+
+use cgmath::InnerSpace;
+use std::path::Path;
+use thiserror::Error;
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+#[derive(Error, Debug)]
+pub enum StlError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("invalid value {0:?}")]
+    Value(String),
+    #[error("unexpected end of file")]
+    UnexpectedEof,
+    #[error("facet {0} doesn't have three vertices")]
+    InvalidFacet(usize),
+    #[error("file is neither binary nor ASCII STL")]
+    UnknownFormat,
+    #[error("file has no facets")]
+    Empty,
+}
+
+type Triangle = ([f32; 3], [[f32; 3]; 3]);
+
+fn read_binary(data: &[u8]) -> Option<Vec<Triangle>> {
+    let count = data.get(HEADER_SIZE..HEADER_SIZE + 4)?;
+    let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+    if data.len() != HEADER_SIZE + 4 + count * TRIANGLE_SIZE {
+        return None;
+    }
+
+    let triangles = data[HEADER_SIZE + 4..]
+        .chunks_exact(TRIANGLE_SIZE)
+        .map(|record| {
+            let [normal, a, b, c] = [0, 1, 2, 3].map(|i| {
+                [0, 1, 2].map(|j| {
+                    let offset = 12 * i + 4 * j;
+                    f32::from_le_bytes(record[offset..offset + 4].try_into().unwrap())
+                })
+            });
+            (normal, [a, b, c])
+        })
+        .collect();
+    Some(triangles)
+}
+
+fn read_vector<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Result<[f32; 3], StlError> {
+    let mut vector = [0.0; 3];
+    for value in &mut vector {
+        let token = tokens.next().ok_or(StlError::UnexpectedEof)?;
+        *value = token
+            .parse()
+            .map_err(|_| StlError::Value(token.to_owned()))?;
+    }
+    Ok(vector)
+}
+
+fn read_ascii(data: &[u8]) -> Result<Vec<Triangle>, StlError> {
+    let text = String::from_utf8_lossy(data);
+    let mut tokens = text.split_ascii_whitespace();
+
+    let mut triangles = Vec::new();
+    let mut normal = [0.0; 3];
+    let mut vertices = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            "normal" => normal = read_vector(&mut tokens)?,
+            "vertex" => vertices.push(read_vector(&mut tokens)?),
+            "endfacet" => {
+                let vertices: [[f32; 3]; 3] = std::mem::take(&mut vertices)
+                    .try_into()
+                    .map_err(|_| StlError::InvalidFacet(triangles.len()))?;
+                triangles.push((std::mem::take(&mut normal), vertices));
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+// Each facet's normal is given to its three vertices, so only vertices shared by
+// faces in the same plane are merged. Facets without a normal get one computed
+// from their winding.
+pub fn load_stl(path: &Path) -> Result<Model, StlError> {
+    parse_stl(&std::fs::read(path)?)
+}
+
+fn parse_stl(data: &[u8]) -> Result<Model, StlError> {
+    let triangles = match read_binary(data) {
+        Some(triangles) => triangles,
+        None if data.trim_ascii_start().starts_with(b"solid") => read_ascii(data)?,
+        None => return Err(StlError::UnknownFormat),
+    };
+    if triangles.is_empty() {
+        return Err(StlError::Empty);
+    }
+
+    let mut vertices = Vec::with_capacity(3 * triangles.len());
+    for (normal, positions) in triangles {
+        let [a, b, c] = positions.map(cgmath::Vector3::from);
+        let mut normal = cgmath::Vector3::from(normal);
+        if normal.magnitude2() == 0.0 {
+            normal = (b - a).cross(c - a);
+        }
+        if normal.magnitude2() > 0.0 {
+            normal = normal.normalize();
+        }
+
+        vertices.extend(positions.map(|position| mesh::Vertex {
+            position: [position[0], position[1], -position[2]],
+            normal: [normal.x, normal.y, -normal.z],
+            uv: [0.0, 0.0],
+            color: [1.0, 1.0, 1.0],
+        }));
+    }
+
+    let mut model = Model {
+        index_format: Model::index_format_for(vertices.len()),
+        index_buffer: (0..vertices.len() as u32).collect(),
+        vertex_buffer: vertices,
+        material_groups: Vec::new(),
+        textures: Vec::new(),
+        lods: Vec::new(),
+    };
+    model.deduplicate_vertices();
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(triangles: &[[[f32; 3]; 4]]) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        data.extend((triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            data.extend(
+                triangle
+                    .as_flattened()
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes()),
+            );
+            data.extend([0, 0]);
+        }
+        data
+    }
+
+    #[test]
+    fn parses_binary_and_ascii() {
+        let normal = [0.0, 0.0, 1.0];
+        let triangle = [normal, [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let model = parse_stl(&binary(&[triangle])).unwrap();
+        assert_eq!(model.vertex_buffer.len(), 3);
+        assert_eq!(model.index_buffer, [0, 1, 2]);
+
+        let ascii = "solid test\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+                     vertex 0 1 0\nendloop\nendfacet\nendsolid test\n";
+        let model = parse_stl(ascii.as_bytes()).unwrap();
+        assert_eq!(model.index_buffer, [0, 1, 2]);
+        assert!(
+            model
+                .vertex_buffer
+                .iter()
+                .all(|vertex| vertex.normal == [0.0, 0.0, -1.0])
+        );
+    }
+
+    #[test]
+    fn rejects_empty_meshes() {
+        assert!(matches!(parse_stl(&binary(&[])), Err(StlError::Empty)));
+        assert!(matches!(
+            parse_stl(b"solid empty\nendsolid empty\n"),
+            Err(StlError::Empty)
+        ));
+    }
+}
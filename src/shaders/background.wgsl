@@ -0,0 +1,25 @@
+struct Colors {
+	@location(0) top: vec4<f32>,
+	@location(1) bottom: vec4<f32>,
+}
+
+struct Fragment {
+	@builtin(position) proj_pos: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> colors: Colors;
+
+@vertex
+fn vert_main(@builtin(vertex_index) index: u32) -> Fragment {
+	var frag: Fragment;
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	frag.proj_pos = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	frag.uv = uv;
+	return frag;
+}
+
+@fragment
+fn frag_main(in: Fragment) -> @location(0) vec4<f32> {
+	return vec4<f32>(mix(colors.top.rgb, colors.bottom.rgb, clamp(in.uv.y, 0.0, 1.0)), 1.0);
+}
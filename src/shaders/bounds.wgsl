@@ -0,0 +1,48 @@
+struct Fragment {
+	@builtin(position) proj_pos: vec4<f32>,
+	@location(0) color: vec4<f32>,
+}
+
+struct Camera {
+	@location(0) view_proj: mat4x4<f32>,
+}
+
+struct Box {
+	@location(0) model: mat4x4<f32>,
+	@location(1) color: vec4<f32>,
+}
+
+// Corner indices hold the unit cube coordinates in their x, y and z bits
+const EDGES: array<vec2<u32>, 12> = array<vec2<u32>, 12>(
+	vec2<u32>(0u, 1u),
+	vec2<u32>(2u, 3u),
+	vec2<u32>(4u, 5u),
+	vec2<u32>(6u, 7u),
+	vec2<u32>(0u, 2u),
+	vec2<u32>(1u, 3u),
+	vec2<u32>(4u, 6u),
+	vec2<u32>(5u, 7u),
+	vec2<u32>(0u, 4u),
+	vec2<u32>(1u, 5u),
+	vec2<u32>(2u, 6u),
+	vec2<u32>(3u, 7u),
+);
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(1) var<storage, read> boxes: array<Box>;
+
+@vertex
+fn vert_main(@builtin(vertex_index) index: u32, @builtin(instance_index) instance: u32) -> Fragment {
+	var frag: Fragment;
+	let edge = EDGES[index / 2u];
+	let corner = select(edge.x, edge.y, index % 2u == 1u);
+	let position = vec3<f32>(vec3<u32>(corner, corner >> 1u, corner >> 2u) & vec3<u32>(1u));
+	frag.proj_pos = camera.view_proj * boxes[instance].model * vec4<f32>(position, 1.0);
+	frag.color = boxes[instance].color;
+	return frag;
+}
+
+@fragment
+fn frag_main(in: Fragment) -> @location(0) vec4<f32> {
+	return in.color;
+}
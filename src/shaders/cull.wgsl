@@ -0,0 +1,72 @@
+struct PointLight {
+	@location(0) position: vec3<f32>,
+	@location(1) radius: f32,
+	@location(2) color: vec3<f32>,
+	@location(3) strength: f32,
+	@location(4) casts_shadow: u32,
+	@location(5) shadow_index: i32,
+	@location(6) shadow_near: f32,
+	@location(7) shadow_far: f32,
+	@location(8) enabled: u32,
+}
+
+struct Tiles {
+	@location(0) inv_view_proj: mat4x4<f32>,
+	@location(1) eye: vec3<f32>,
+	@location(2) light_count: u32,
+	@location(3) screen_size: vec2<f32>,
+	@location(4) tile_count: vec2<u32>,
+	@location(5) origin: vec2<f32>,
+}
+
+const TILE_SIZE: u32 = 16u;
+const MAX_TILE_LIGHTS: u32 = 63u;
+
+@group(0) @binding(0) var<uniform> tiles: Tiles;
+@group(0) @binding(1) var<storage> point_lights: array<PointLight>;
+@group(0) @binding(2) var<storage, read_write> tile_lights: array<u32>;
+
+fn tile_ray(pixel: vec2<f32>) -> vec3<f32> {
+	let ndc = vec2<f32>(pixel.x / tiles.screen_size.x * 2.0 - 1.0, 1.0 - pixel.y / tiles.screen_size.y * 2.0);
+	let point = tiles.inv_view_proj * vec4<f32>(ndc, 0.5, 1.0);
+	return point.xyz / point.w - tiles.eye;
+}
+
+@compute @workgroup_size(8, 8)
+fn cull_main(@builtin(global_invocation_id) id: vec3<u32>) {
+	if (any(id.xy >= tiles.tile_count)) {
+		return;
+	}
+
+	let min_pixel = vec2<f32>(id.xy * TILE_SIZE);
+	let max_pixel = min(min_pixel + f32(TILE_SIZE), tiles.screen_size);
+	let corners = array<vec3<f32>, 4>(
+		tile_ray(min_pixel),
+		tile_ray(vec2<f32>(max_pixel.x, min_pixel.y)),
+		tile_ray(max_pixel),
+		tile_ray(vec2<f32>(min_pixel.x, max_pixel.y)),
+	);
+	let center = tile_ray((min_pixel + max_pixel) * 0.5);
+
+	var planes: array<vec3<f32>, 4>;
+	for (var i = 0u; i < 4u; i++) {
+		let normal = normalize(cross(corners[i], corners[(i + 1u) % 4u]));
+		planes[i] = select(normal, -normal, dot(normal, center) < 0.0);
+	}
+
+	let base = (id.y * tiles.tile_count.x + id.x) * (MAX_TILE_LIGHTS + 1u);
+	var count = 0u;
+	for (var i = 0u; i < tiles.light_count && count < MAX_TILE_LIGHTS; i++) {
+		let light = point_lights[i];
+		let d = light.position - tiles.eye;
+		var visible = light.enabled != 0u;
+		for (var j = 0u; j < 4u; j++) {
+			visible = visible && dot(planes[j], d) > -light.radius;
+		}
+		if (visible) {
+			count++;
+			tile_lights[base + count] = i;
+		}
+	}
+	tile_lights[base] = count;
+}
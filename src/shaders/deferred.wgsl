@@ -0,0 +1,30 @@
+struct Fragment {
+	@builtin(position) proj_pos: vec4<f32>,
+}
+
+@group(0) @binding(1) var gbuffer_position: texture_2d<f32>;
+@group(0) @binding(2) var gbuffer_normal: texture_2d<f32>;
+@group(0) @binding(3) var gbuffer_emissive: texture_2d<f32>;
+@group(0) @binding(4) var gbuffer_albedo: texture_2d<f32>;
+
+@vertex
+fn vert_main(@builtin(vertex_index) index: u32) -> Fragment {
+	var frag: Fragment;
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	frag.proj_pos = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	return frag;
+}
+
+@fragment
+fn frag_main(in: Fragment) -> @location(0) vec4<f32> {
+	let coords = vec2<i32>(in.proj_pos.xy);
+	let emissive = textureLoad(gbuffer_emissive, coords, 0);
+	if (emissive.a == 0.0) {
+		discard;
+	}
+
+	let position = textureLoad(gbuffer_position, coords, 0);
+	let normal = textureLoad(gbuffer_normal, coords, 0);
+	let albedo = textureLoad(gbuffer_albedo, coords, 0);
+	return shade(in.proj_pos.xy, position.xyz, normal.xyz, position.w, normal.w, emissive.rgb, albedo.rgb);
+}
@@ -0,0 +1,72 @@
+struct Fragment {
+	@builtin(position) proj_pos: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0) var input_texture: texture_2d<f32>;
+@group(0) @binding(1) var input_sampler: sampler;
+
+const EDGE_THRESHOLD_MIN: f32 = 0.0312;
+const EDGE_THRESHOLD_MAX: f32 = 0.125;
+const REDUCE_MIN: f32 = 1.0 / 128.0;
+const REDUCE_MUL: f32 = 1.0 / 8.0;
+const SPAN_MAX: f32 = 8.0;
+
+@vertex
+fn vert_main(@builtin(vertex_index) index: u32) -> Fragment {
+	var frag: Fragment;
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	frag.proj_pos = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	frag.uv = uv;
+	return frag;
+}
+
+fn sample(uv: vec2<f32>) -> vec3<f32> {
+	return textureSampleLevel(input_texture, input_sampler, uv, 0.0).rgb;
+}
+
+// The sRGB transfer function, undoing the decode done when the input is sampled
+fn srgb_encode(color: vec3<f32>) -> vec3<f32> {
+	let c = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+	return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+// Contrast is measured on gamma-encoded luma, the perceptual scale the edge
+// thresholds are tuned for
+fn luma(color: vec3<f32>) -> f32 {
+	return dot(srgb_encode(color), vec3<f32>(0.299, 0.587, 0.114));
+}
+
+@fragment
+fn frag_main(in: Fragment) -> @location(0) vec4<f32> {
+	let texel = 1.0 / vec2<f32>(textureDimensions(input_texture));
+	let rgb_m = sample(in.uv);
+	let luma_m = luma(rgb_m);
+	let luma_nw = luma(sample(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+	let luma_ne = luma(sample(in.uv + vec2<f32>(1.0, -1.0) * texel));
+	let luma_sw = luma(sample(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+	let luma_se = luma(sample(in.uv + vec2<f32>(1.0, 1.0) * texel));
+
+	let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+	let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+	if (luma_max - luma_min < max(EDGE_THRESHOLD_MIN, luma_max * EDGE_THRESHOLD_MAX)) {
+		return vec4<f32>(rgb_m, 1.0);
+	}
+
+	var dir = vec2<f32>(
+		-((luma_nw + luma_ne) - (luma_sw + luma_se)),
+		(luma_nw + luma_sw) - (luma_ne + luma_se),
+	);
+	let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+	let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+	dir = clamp(dir * rcp_dir_min, vec2<f32>(-SPAN_MAX), vec2<f32>(SPAN_MAX)) * texel;
+
+	let rgb_a = 0.5 * (sample(in.uv + dir * (1.0 / 3.0 - 0.5)) + sample(in.uv + dir * (2.0 / 3.0 - 0.5)));
+	let rgb_b = rgb_a * 0.5 + 0.25 * (sample(in.uv - dir * 0.5) + sample(in.uv + dir * 0.5));
+	let luma_b = luma(rgb_b);
+	if (luma_b < luma_min || luma_b > luma_max) {
+		return vec4<f32>(rgb_a, 1.0);
+	}
+
+	return vec4<f32>(rgb_b, 1.0);
+}
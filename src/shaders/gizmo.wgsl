@@ -0,0 +1,35 @@
+struct Fragment {
+	@builtin(position) proj_pos: vec4<f32>,
+	@location(0) color: vec3<f32>,
+}
+
+struct Gizmo {
+	@location(0) rotation: mat4x4<f32>,
+}
+
+const AXIS_SCALE: f32 = 0.8;
+
+const AXIS_COLORS: array<vec3<f32>, 3> = array<vec3<f32>, 3>(
+	vec3<f32>(1.0, 0.2, 0.2),
+	vec3<f32>(0.2, 1.0, 0.2),
+	vec3<f32>(0.2, 0.4, 1.0),
+);
+
+@group(0) @binding(0) var<uniform> gizmo: Gizmo;
+
+@vertex
+fn vert_main(@builtin(vertex_index) index: u32) -> Fragment {
+	var frag: Fragment;
+	let axis = index / 2u;
+	var direction = vec3<f32>(0.0);
+	direction[axis] = f32(index % 2u);
+	let v = (gizmo.rotation * vec4<f32>(direction, 0.0)).xyz;
+	frag.proj_pos = vec4<f32>(v.xy * AXIS_SCALE, 0.5 - v.z * 0.25, 1.0);
+	frag.color = AXIS_COLORS[axis];
+	return frag;
+}
+
+@fragment
+fn frag_main(in: Fragment) -> @location(0) vec4<f32> {
+	return vec4<f32>(in.color, 1.0);
+}
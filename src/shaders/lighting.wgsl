@@ -0,0 +1,202 @@
+struct PointLight {
+	@location(0) position: vec3<f32>,
+	@location(1) radius: f32,
+	@location(2) color: vec3<f32>,
+	@location(3) strength: f32,
+	@location(4) casts_shadow: u32,
+	@location(5) shadow_index: i32,
+	@location(6) shadow_near: f32,
+	@location(7) shadow_far: f32,
+	@location(8) enabled: u32,
+}
+
+struct DirectionalLight {
+	@location(0) position: vec3<f32>,
+	@location(1) direction: vec3<f32>,
+	@location(2) color: vec3<f32>,
+	@location(3) strength: f32,
+	@location(4) casts_shadow: u32,
+	@location(5) shadow_index: i32,
+	@location(6) enabled: u32,
+}
+
+struct Camera {
+	@location(0) position: vec3<f32>,
+	@location(1) far: f32,
+	@location(2) view_proj: mat4x4<f32>,
+	@location(3) near: f32,
+}
+
+struct Cascades {
+	@location(0) view_projs: array<mat4x4<f32>, 16>,
+	@location(1) splits: vec4<f32>,
+	@location(2) count: u32,
+}
+
+struct Tiles {
+	@location(0) inv_view_proj: mat4x4<f32>,
+	@location(1) eye: vec3<f32>,
+	@location(2) light_count: u32,
+	@location(3) screen_size: vec2<f32>,
+	@location(4) tile_count: vec2<u32>,
+	@location(5) origin: vec2<f32>,
+}
+
+struct Globals {
+	@location(0) debug_mode: u32,
+	@location(1) selection_color: vec4<f32>,
+	@location(2) wireframe_color: vec4<f32>,
+	@location(3) ambient_color: vec3<f32>,
+	@location(4) ambient_intensity: f32,
+}
+
+const DEBUG_SHADED: u32 = 0u;
+const DEBUG_NORMALS: u32 = 1u;
+const DEBUG_DEPTH: u32 = 2u;
+const DEBUG_UVS: u32 = 3u;
+const DEBUG_METALLIC: u32 = 4u;
+const DEBUG_TANGENTS: u32 = 5u;
+const DEBUG_BITANGENTS: u32 = 6u;
+const DEBUG_CASCADES: u32 = 7u;
+const DEBUG_CLIP_PLANES: u32 = 8u;
+const DEBUG_UNLIT: u32 = 9u;
+
+const TILE_SIZE: u32 = 16u;
+const MAX_TILE_LIGHTS: u32 = 63u;
+
+const POINT_SHADOW_NORMAL_OFFSET: f32 = 0.02;
+const DIRECTIONAL_SHADOW_NORMAL_OFFSET: f32 = 0.05;
+
+// Fragments closer than this factor of the near plane, or beyond this fraction of the far plane, are tinted
+const NEAR_TINT_RANGE: f32 = 4.0;
+const FAR_TINT_RANGE: f32 = 0.9;
+const NEAR_TINT: vec3<f32> = vec3<f32>(1.0, 0.2, 0.2);
+const FAR_TINT: vec3<f32> = vec3<f32>(0.2, 0.4, 1.0);
+
+// Unlit shading scales a uniform albedo by the material color
+const UNLIT_ALBEDO: vec3<f32> = vec3<f32>(0.8, 0.8, 0.8);
+
+const CASCADE_COLORS: array<vec3<f32>, 4> = array<vec3<f32>, 4>(
+	vec3<f32>(1.0, 0.3, 0.3),
+	vec3<f32>(0.3, 1.0, 0.3),
+	vec3<f32>(0.3, 0.3, 1.0),
+	vec3<f32>(1.0, 1.0, 0.3),
+);
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+@group(1) @binding(0) var<storage> point_lights: array<PointLight>;
+@group(1) @binding(1) var<storage> directional_lights: array<DirectionalLight>;
+@group(1) @binding(2) var<uniform> globals: Globals;
+@group(1) @binding(3) var point_shadows: texture_depth_cube_array;
+@group(1) @binding(4) var shadow_sampler: sampler_comparison;
+@group(1) @binding(5) var directional_shadows: texture_depth_2d_array;
+@group(1) @binding(6) var<uniform> cascades: Cascades;
+@group(1) @binding(7) var<storage> tile_lights: array<u32>;
+@group(1) @binding(8) var<uniform> tiles: Tiles;
+
+fn diffuse(l: vec3<f32>, n: vec3<f32>) -> f32 {
+	return clamp(dot(l, n), 0.0, 1.0);
+}
+
+fn specular(l: vec3<f32>, v: vec3<f32>, n: vec3<f32>) -> f32 {
+	let r = reflect(-l, n);
+	return clamp(dot(r, v), 0.0, 1.0);
+}
+
+fn range_falloff(r: f32, radius: f32) -> f32 {
+	let x = clamp(1.0 - pow(r / radius, 4.0), 0.0, 1.0);
+	return x * x;
+}
+
+fn point_shadow(light: PointLight, world_pos: vec3<f32>, n: vec3<f32>) -> f32 {
+	if (light.shadow_index < 0) {
+		return 1.0;
+	}
+
+	let d = world_pos + n * POINT_SHADOW_NORMAL_OFFSET - light.position;
+	let z = max(abs(d.x), max(abs(d.y), abs(d.z)));
+	let depth = light.shadow_far * (z - light.shadow_near) / ((light.shadow_far - light.shadow_near) * z);
+	return textureSampleCompareLevel(point_shadows, shadow_sampler, d, light.shadow_index, depth);
+}
+
+fn cascade_index(view_depth: f32) -> u32 {
+	var i = 0u;
+	while (i + 1u < cascades.count && view_depth > cascades.splits[i]) {
+		i++;
+	}
+	return i;
+}
+
+fn directional_shadow(light: DirectionalLight, world_pos: vec3<f32>, n: vec3<f32>, view_depth: f32) -> f32 {
+	if (light.shadow_index < 0) {
+		return 1.0;
+	}
+
+	let layer = u32(light.shadow_index) * cascades.count + cascade_index(view_depth);
+	let pos = cascades.view_projs[layer] * vec4<f32>(world_pos + n * DIRECTIONAL_SHADOW_NORMAL_OFFSET, 1.0);
+	let uv = pos.xy * vec2<f32>(0.5, -0.5) + 0.5;
+	if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || pos.z > 1.0) {
+		return 1.0;
+	}
+	return textureSampleCompareLevel(directional_shadows, shadow_sampler, uv, layer, pos.z);
+}
+
+fn shade(frag_coord: vec2<f32>, world_pos: vec3<f32>, n: vec3<f32>, view_depth: f32, metallic: f32, emissive: vec3<f32>, albedo: vec3<f32>) -> vec4<f32> {
+	switch globals.debug_mode {
+		case DEBUG_NORMALS: {
+			return vec4<f32>(n * 0.5 + 0.5, 1.0);
+		}
+		case DEBUG_DEPTH: {
+			return vec4<f32>(vec3<f32>(clamp(view_depth / camera.far, 0.0, 1.0)), 1.0);
+		}
+		case DEBUG_METALLIC: {
+			return vec4<f32>(vec3<f32>(metallic), 1.0);
+		}
+		case DEBUG_UNLIT: {
+			return vec4<f32>(UNLIT_ALBEDO * albedo + emissive, 1.0);
+		}
+		default: {}
+	}
+
+	var result: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
+	let tile = min(vec2<u32>(frag_coord - tiles.origin) / TILE_SIZE, tiles.tile_count - 1u);
+	let base = (tile.y * tiles.tile_count.x + tile.x) * (MAX_TILE_LIGHTS + 1u);
+	for (var i = 0u; i < tile_lights[base]; i++) {
+		let light = point_lights[tile_lights[base + i + 1u]];
+		let r = distance(light.position, world_pos);
+		if (r > light.radius) {
+			continue;
+		}
+
+		let l = normalize(light.position - world_pos);
+		let v = normalize(camera.position - world_pos);
+		let shadow = point_shadow(light, world_pos, n);
+		result += mix(albedo * diffuse(l, n), vec3<f32>(specular(l, v, n)), metallic) * light.color * light.strength * shadow * range_falloff(r, light.radius) * (1.0 / (r * r + 1.0));
+	}
+
+	for (var i = 0u; i < arrayLength(&directional_lights); i++) {
+		let light = directional_lights[i];
+		if (light.enabled == 0u) {
+			continue;
+		}
+
+		let l = -light.direction;
+		let v = normalize(camera.position - world_pos);
+		let r = distance(light.position, world_pos);
+		let shadow = directional_shadow(light, world_pos, n, view_depth);
+		result += mix(albedo * diffuse(l, n), vec3<f32>(specular(l, v, n)), metallic) * light.color * light.strength * shadow * (1.0 / (r * r + 1.0));
+	}
+
+	result += albedo * globals.ambient_color * globals.ambient_intensity;
+	result += emissive;
+	if (globals.debug_mode == DEBUG_CASCADES) {
+		result *= CASCADE_COLORS[cascade_index(view_depth)];
+	}
+	if (globals.debug_mode == DEBUG_CLIP_PLANES) {
+		let near_band = 1.0 - smoothstep(camera.near, camera.near * NEAR_TINT_RANGE, view_depth);
+		let far_band = smoothstep(camera.far * FAR_TINT_RANGE, camera.far, view_depth);
+		result = mix(mix(result, NEAR_TINT, near_band), FAR_TINT, far_band);
+	}
+	return vec4<f32>(result, 1.0);
+}
@@ -2,42 +2,57 @@ struct Vertex {
 	@location(0) pos: vec3<f32>,
 	@location(1) normal: vec3<f32>,
 	@location(2) uv: vec2<f32>,
+	@location(3) color: vec3<f32>,
 }
 
 struct Fragment {
 	@builtin(position) proj_pos: vec4<f32>,
 	@location(0) world_pos: vec4<f32>,
 	@location(1) normal: vec3<f32>,
+	@location(2) uv: vec2<f32>,
+	@location(3) view_depth: f32,
+	@location(4) color: vec3<f32>,
 }
 
-struct PointLight {
-	@location(0) position: vec3<f32>,
-	@location(1) color: vec3<f32>,
-	@location(2) strength: f32,
-}
-
-struct DirectionalLight {
-	@location(0) position: vec3<f32>,
-	@location(1) direction: vec3<f32>,
-	@location(2) color: vec3<f32>,
-	@location(3) strength: f32,
+struct GBuffer {
+	@location(0) position: vec4<f32>,
+	@location(1) normal: vec4<f32>,
+	@location(2) emissive: vec4<f32>,
+	@location(3) albedo: vec4<f32>,
 }
 
 struct Object {
 	@location(0) model: mat4x4<f32>,
+	@location(1) emissive: vec3<f32>,
 	@location(2) metallic: f32,
+	@location(3) alpha_cutoff: f32,
 }
 
-struct Camera {
-	@location(0) position: vec3<f32>,
-	@location(1) view_proj: mat4x4<f32>,
+struct Material {
+	color: vec3<f32>,
+	metallic: f32,
 }
 
-@group(0) @binding(0) var<uniform> camera: Camera;
+const DEGENERATE_COLOR: vec3<f32> = vec3<f32>(1.0, 0.0, 1.0);
+
 @group(0) @binding(1) var<uniform> object: Object;
+@group(2) @binding(0) var<uniform> material: Material;
+@group(2) @binding(1) var material_texture: texture_2d<f32>;
+@group(1) @binding(9) var material_sampler: sampler;
 
-@group(1) @binding(0) var<storage> point_lights: array<PointLight>;
-@group(1) @binding(1) var<storage> directional_lights: array<DirectionalLight>;
+// Materials without a metallic value of their own use the object's
+fn material_metallic() -> f32 {
+	return select(object.metallic, material.metallic, material.metallic >= 0.0);
+}
+
+// Discards cutout fragments and returns the albedo for the rest
+fn material_albedo(uv: vec2<f32>) -> vec3<f32> {
+	let texel = textureSample(material_texture, material_sampler, uv);
+	if (texel.a < object.alpha_cutoff) {
+		discard;
+	}
+	return material.color * texel.rgb;
+}
 
 @vertex
 fn vert_main(in: Vertex) -> Fragment {
@@ -45,37 +60,72 @@ fn vert_main(in: Vertex) -> Fragment {
 	frag.world_pos = object.model * vec4(in.pos, 1.0);
 	frag.normal = mat3x3<f32>(object.model[0].xyz, object.model[1].xyz, object.model[2].xyz) * in.normal;
 	frag.proj_pos = camera.view_proj * frag.world_pos;
+	frag.uv = in.uv;
+	frag.view_depth = frag.proj_pos.w;
+	frag.color = in.color;
 	return frag;
 }
 
-fn diffuse(l: vec3<f32>, n: vec3<f32>) -> f32 {
-	return clamp(dot(l, n), 0.0, 1.0);
-}
+fn tangent_debug(world_pos: vec3<f32>, uv: vec2<f32>, n: vec3<f32>, bitangent: bool) -> vec4<f32> {
+	let dp1 = dpdx(world_pos);
+	let dp2 = dpdy(world_pos);
+	let duv1 = dpdx(uv);
+	let duv2 = dpdy(uv);
+	let det = duv1.x * duv2.y - duv2.x * duv1.y;
+	if (abs(det) < 1e-12) {
+		return vec4<f32>(DEGENERATE_COLOR, 1.0);
+	}
 
-fn specular(l: vec3<f32>, v: vec3<f32>, n: vec3<f32>) -> f32 {
-	let r = reflect(-l, n);
-	return clamp(dot(r, v), 0.0, 1.0);
+	var v: vec3<f32>;
+	if (bitangent) {
+		v = (dp2 * duv1.x - dp1 * duv2.x) / det;
+	} else {
+		v = (dp1 * duv2.y - dp2 * duv1.y) / det;
+	}
+	v = v - n * dot(n, v);
+	if (length(v) < 1e-6) {
+		return vec4<f32>(DEGENERATE_COLOR, 1.0);
+	}
+	return vec4<f32>(normalize(v) * 0.5 + 0.5, 1.0);
 }
 
 @fragment
 fn frag_main(in: Fragment) -> @location(0) vec4<f32> {
+	let albedo = material_albedo(in.uv) * in.color;
 	let n = normalize(in.normal);
-	var result: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
-	for (var i = 0u; i < arrayLength(&point_lights); i++) {
-		let light = point_lights[i];
-		let l = normalize(light.position - in.world_pos.xyz);
-		let v = normalize(camera.position - in.world_pos.xyz);
-		let r = distance(light.position, in.world_pos.xyz);
-		result += mix(diffuse(l, n), specular(l, v, n), object.metallic) * light.color * light.strength * (1.0 / (r * r + 1.0));
+	switch globals.debug_mode {
+		case DEBUG_UVS: {
+			return vec4<f32>(in.uv, 0.0, 1.0);
+		}
+		case DEBUG_TANGENTS: {
+			return tangent_debug(in.world_pos.xyz, in.uv, n, false);
+		}
+		case DEBUG_BITANGENTS: {
+			return tangent_debug(in.world_pos.xyz, in.uv, n, true);
+		}
+		default: {}
 	}
 
-	for (var i = 0u; i < arrayLength(&directional_lights); i++) {
-		let light = directional_lights[i];
-		let l = -light.direction;
-		let v = normalize(camera.position - in.world_pos.xyz);
-		let r = distance(light.position, in.world_pos.xyz);
-		result += mix(diffuse(l, n), specular(l, v, n), object.metallic) * light.color * light.strength * (1.0 / (r * r + 1.0));
-	}
+	return shade(in.proj_pos.xy, in.world_pos.xyz, n, in.view_depth, material_metallic(), object.emissive, albedo);
+}
+
+@fragment
+fn gbuffer_main(in: Fragment) -> GBuffer {
+	let albedo = material_albedo(in.uv) * in.color;
+	var out: GBuffer;
+	out.position = vec4<f32>(in.world_pos.xyz, in.view_depth);
+	out.normal = vec4<f32>(normalize(in.normal), material_metallic());
+	out.emissive = vec4<f32>(object.emissive, 1.0);
+	out.albedo = vec4<f32>(albedo, 1.0);
+	return out;
+}
 
-	return vec4<f32>(result, 1.0);
+@fragment
+fn overlay_main(in: Fragment) -> @location(0) vec4<f32> {
+	return globals.selection_color;
+}
+
+@fragment
+fn wireframe_main(in: Fragment) -> @location(0) vec4<f32> {
+	return globals.wireframe_color;
 }
@@ -0,0 +1,44 @@
+struct Camera {
+	@location(0) position: vec3<f32>,
+	@location(1) far: f32,
+	@location(2) view_proj: mat4x4<f32>,
+	@location(3) near: f32,
+}
+
+struct Object {
+	@location(0) model: mat4x4<f32>,
+	@location(1) emissive: vec3<f32>,
+	@location(2) metallic: f32,
+	@location(3) alpha_cutoff: f32,
+}
+
+struct Lines {
+	@location(0) length: f32,
+}
+
+struct Vertex {
+	@location(0) pos: vec3<f32>,
+	@location(1) normal: vec3<f32>,
+	@location(2) uv: vec2<f32>,
+}
+
+const LINE_COLOR: vec3<f32> = vec3<f32>(0.2, 0.4, 1.0);
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(1) var<uniform> object: Object;
+@group(1) @binding(0) var<uniform> lines: Lines;
+
+// Each line is two vertices, the first at the vertex and the second at the end
+// of its normal. Zero normals collapse to a point.
+@vertex
+fn vert_main(@builtin(vertex_index) index: u32, vertex: Vertex) -> @builtin(position) vec4<f32> {
+	let normal = mat3x3<f32>(object.model[0].xyz, object.model[1].xyz, object.model[2].xyz) * vertex.normal;
+	let direction = select(vec3<f32>(0.0), normalize(normal), dot(normal, normal) > 0.0);
+	let pos = (object.model * vec4<f32>(vertex.pos, 1.0)).xyz + direction * lines.length * f32(index);
+	return camera.view_proj * vec4<f32>(pos, 1.0);
+}
+
+@fragment
+fn frag_main() -> @location(0) vec4<f32> {
+	return vec4<f32>(LINE_COLOR, 1.0);
+}
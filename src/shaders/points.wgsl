@@ -0,0 +1,58 @@
+struct Camera {
+	@location(0) position: vec3<f32>,
+	@location(1) far: f32,
+	@location(2) view_proj: mat4x4<f32>,
+	@location(3) near: f32,
+}
+
+struct Object {
+	@location(0) model: mat4x4<f32>,
+	@location(1) emissive: vec3<f32>,
+	@location(2) metallic: f32,
+	@location(3) alpha_cutoff: f32,
+}
+
+struct Sprites {
+	@location(0) target_size: vec2<f32>,
+	@location(1) point_size: f32,
+}
+
+struct Point {
+	@location(0) pos: vec3<f32>,
+	@location(1) normal: vec3<f32>,
+	@location(2) uv: vec2<f32>,
+	@location(3) color: vec3<f32>,
+}
+
+struct Fragment {
+	@builtin(position) proj_pos: vec4<f32>,
+	@location(0) corner: vec2<f32>,
+	@location(1) color: vec3<f32>,
+}
+
+const POINT_COLOR: vec3<f32> = vec3<f32>(0.8, 0.8, 0.8);
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(1) var<uniform> object: Object;
+@group(1) @binding(0) var<uniform> sprites: Sprites;
+
+// Each point is a quad drawn as a four vertex strip, offset in clip space so
+// that it keeps the same size in pixels at any distance
+@vertex
+fn vert_main(@builtin(vertex_index) index: u32, point: Point) -> Fragment {
+	var frag: Fragment;
+	frag.corner = vec2<f32>(f32(index & 1u), f32(index >> 1u)) * 2.0 - 1.0;
+	frag.color = point.color;
+	frag.proj_pos = camera.view_proj * object.model * vec4<f32>(point.pos, 1.0);
+	frag.proj_pos.x += frag.corner.x * sprites.point_size / sprites.target_size.x * frag.proj_pos.w;
+	frag.proj_pos.y += frag.corner.y * sprites.point_size / sprites.target_size.y * frag.proj_pos.w;
+	return frag;
+}
+
+@fragment
+fn frag_main(in: Fragment) -> @location(0) vec4<f32> {
+	if (dot(in.corner, in.corner) > 1.0) {
+		discard;
+	}
+	return vec4<f32>(POINT_COLOR * in.color + object.emissive, 1.0);
+}
@@ -0,0 +1,24 @@
+struct Fragment {
+	@builtin(position) proj_pos: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0) var input_texture: texture_2d<f32>;
+@group(0) @binding(1) var input_sampler: sampler;
+
+@vertex
+fn vert_main(@builtin(vertex_index) index: u32) -> Fragment {
+	var frag: Fragment;
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	frag.proj_pos = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	frag.uv = uv;
+	return frag;
+}
+
+// When downsampling, a bilinear tap at the output pixel center averages the
+// 2x2 input texels it covers, which is an exact box filter at twice the output
+// resolution. When upsampling, the sampler's mag filter picks the texels.
+@fragment
+fn frag_main(in: Fragment) -> @location(0) vec4<f32> {
+	return vec4<f32>(textureSampleLevel(input_texture, input_sampler, in.uv, 0.0).rgb, 1.0);
+}
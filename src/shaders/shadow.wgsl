@@ -0,0 +1,23 @@
+struct Vertex {
+	@location(0) pos: vec3<f32>,
+}
+
+struct Object {
+	@location(0) model: mat4x4<f32>,
+	@location(1) emissive: vec3<f32>,
+	@location(2) metallic: f32,
+	@location(3) alpha_cutoff: f32,
+}
+
+struct Face {
+	@location(0) view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(1) var<uniform> object: Object;
+
+@group(1) @binding(0) var<uniform> face: Face;
+
+@vertex
+fn vert_main(in: Vertex) -> @builtin(position) vec4<f32> {
+	return face.view_proj * object.model * vec4(in.pos, 1.0);
+}
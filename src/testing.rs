@@ -0,0 +1,97 @@
+use crate::scene::{InitData, Model, ModelLoadOptions, Scene};
+
+use std::path::Path;
+
+pub const RENDER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+pub const TEST_SIZE: (u32, u32) = (64, 64);
+
+async fn request_adapter(backends: wgpu::Backends) -> Result<wgpu::Adapter, anyhow::Error> {
+    let instance_descriptor = wgpu::InstanceDescriptor {
+        backends,
+        flags: wgpu::InstanceFlags::debugging(),
+        backend_options: wgpu::BackendOptions {
+            noop: wgpu::NoopBackendOptions { enable: true },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let instance = wgpu::Instance::new(&instance_descriptor);
+
+    let request_adapter_options = wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        compatible_surface: None,
+        ..Default::default()
+    };
+    Ok(instance.request_adapter(&request_adapter_options).await?)
+}
+
+// Machines without a GPU get the no-op backend, which validates every call but
+// never executes anything, so results can't be read back from it
+pub async fn test_device() -> Result<(wgpu::Device, wgpu::Queue), anyhow::Error> {
+    let adapter = match request_adapter(wgpu::Backends::PRIMARY).await {
+        Ok(adapter) => adapter,
+        Err(_) => request_adapter(wgpu::Backends::NOOP).await?,
+    };
+
+    let device_descriptor = wgpu::DeviceDescriptor {
+        label: Some("Test Device"),
+        required_features: wgpu::Features::empty(),
+        memory_hints: wgpu::MemoryHints::Performance,
+        ..Default::default()
+    };
+    let (device, queue) = adapter.request_device(&device_descriptor).await?;
+    Ok((device, queue))
+}
+
+pub fn test_config(width: u32, height: u32) -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        present_mode: wgpu::PresentMode::AutoVsync,
+        width,
+        height,
+        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+        desired_maximum_frame_latency: 2,
+        format: RENDER_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: vec![],
+    }
+}
+
+// A row of cubes from the bundled assets, one object per model
+pub fn test_scene(model_count: usize) -> (InitData, Scene) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/cube.obj");
+    let model = Model::load_obj(&path, &ModelLoadOptions::default()).unwrap();
+    let init_data = InitData {
+        models: vec![model; model_count],
+    };
+
+    let mut scene = Scene::new(1.0, cgmath::Point3::new(0.0, 2.0, -5.0));
+    scene.lay_out_objects(model_count);
+    scene.model_bounds = init_data.models.iter().map(Model::bounds).collect();
+    (init_data, scene)
+}
+
+pub fn test_target(device: &wgpu::Device) -> (wgpu::TextureView, wgpu::TextureView) {
+    let create_view = |format, label| {
+        let texture_descriptor = wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: TEST_SIZE.0,
+                height: TEST_SIZE.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        };
+        device
+            .create_texture(&texture_descriptor)
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    };
+    (
+        create_view(RENDER_FORMAT, "Test Color Texture"),
+        create_view(wgpu::TextureFormat::Depth32Float, "Test Depth Texture"),
+    )
+}